@@ -4,13 +4,13 @@ use vidya_rust::camera::Targetable;
 use vidya_rust::extensions::*;
 use vidya_rust::animation::{AnimationSetBundle, AnimationSet, Animation, AnimationTimer};
 use vidya_rust::game::GamePlugins;
-use vidya_rust::map::MapScreenType;
+use vidya_rust::map::{MapScreenType, MapObjectEvent};
 use vidya_rust::platformer::{Platformer, PlatformerAnimator};
 use vidya_rust::direction::{DirectionState, DirectionType};
 use vidya_rust::physics::{Friction, Position, CylinderShape, Weight, PhysicsBundle, WallState, Caster};
 use vidya_rust::player::Player;
 use vidya_rust::game::GameState;
-use vidya_rust::screen::{LoadScreenEvent, ScreenLoadedEvent};
+use vidya_rust::screen::LoadScreenEvent;
 use vidya_rust::state::ActionState;
 
 use bevy::prelude::*;
@@ -41,16 +41,16 @@ fn load_map(mut writer: EventWriter<LoadScreenEvent>) {
 // Spawns player after map finishes loading
 fn spawn_player(
     assets: Res<AssetServer>,
-    mut events: EventReader<ScreenLoadedEvent>,
+    mut events: EventReader<MapObjectEvent>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut commands: Commands
 ) {
 
-    // Waits for the map to fully spawn
-    match events.iter().next() {
+    // Waits for the map to announce the player's spawn point
+    let spawn_point = match events.iter().find(|event| event.0.name == "player") {
         None => return,
-        Some(_) => {}
-    }
+        Some(event) => event.0.position
+    };
 
     // Loads material from single image
     let player_mat = StandardMaterial::from_image("player/char_a_p1_0bas_demn_v01.png", AlphaMode::Mask(0.5), &assets);
@@ -76,7 +76,7 @@ fn spawn_player(
     let jump_handle = animation_set.add_animation_group(&[jump_e, jump_n, jump_w, jump_s]);
 
     let mut pb = PhysicsBundle::new(
-        Position(Vec3::new(200.0, 40.0, -360.0)),
+        Position(spawn_point + Vec3::new(0.0, 40.0, 0.0)),
         CylinderShape {
             radius: 6.0,
             half_height: 15.0
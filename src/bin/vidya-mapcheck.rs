@@ -0,0 +1,77 @@
+//! Checks TMX maps for problems without launching the game.
+//! Runs every map through the same pipeline used when loading it, and reports every problem found.
+//!
+//! Usage: vidya-mapcheck [--flip-y] <map.tmx>...
+//! Exits with a non-zero status if any map has problems.
+
+use std::path::Path;
+use std::process::ExitCode;
+
+use tiled::{FilesystemResourceCache, Loader};
+use vidya_rust::map::{check_tiled_map, MapLoadError};
+
+fn main() -> ExitCode {
+    let mut flip_y = false;
+    let mut paths = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--flip-y" => flip_y = true,
+            "-h" | "--help" => {
+                print_usage();
+                return ExitCode::SUCCESS;
+            }
+            _ => paths.push(arg)
+        }
+    }
+    if paths.is_empty() {
+        print_usage();
+        return ExitCode::from(2);
+    }
+
+    // Checks every map, even after one of them has problems
+    let mut problem_count = 0;
+    for path in &paths {
+        let errors = check_map(Path::new(path), flip_y);
+        for error in &errors {
+            println!("{}: {}", path, error);
+        }
+        problem_count += errors.len();
+    }
+
+    // Summarizes
+    if problem_count == 0 {
+        println!("No problems found in {} map(s)", paths.len());
+        ExitCode::SUCCESS
+    }
+    else {
+        println!("Found {} problem(s) in {} map(s)", problem_count, paths.len());
+        ExitCode::FAILURE
+    }
+}
+
+// Loads a map and checks it, along with the images of its tilesets. A map that can't be parsed is a single problem.
+fn check_map(path: &Path, flip_y: bool) -> Vec<MapLoadError> {
+    let mut loader = Loader::with_cache(FilesystemResourceCache::new());
+    let tiled_map = match loader.load_tmx_map(path) {
+        Ok(tiled_map) => tiled_map,
+        Err(error) => return vec![MapLoadError::from(error)]
+    };
+    let mut errors = Vec::new();
+    for tileset in tiled_map.tilesets() {
+        let tile_images = tileset.tiles().filter_map(|(_, tile)| tile.image.clone());
+        for image in tileset.image.clone().into_iter().chain(tile_images) {
+            if !image.source.is_file() {
+                errors.push(MapLoadError::Io {
+                    path: image.source,
+                    message: format!("Image of tileset '{}' not found", tileset.name)
+                });
+            }
+        }
+    }
+    errors.extend(check_tiled_map(&tiled_map, flip_y));
+    errors
+}
+
+fn print_usage() {
+    eprintln!("Usage: vidya-mapcheck [--flip-y] <map.tmx>...");
+}
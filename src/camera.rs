@@ -79,6 +79,12 @@ impl GameCameraBundle {
             main: MainCamera
         }
     }
+
+    /// Sets the color the camera clears the screen with
+    pub fn with_clear_color(mut self, clear_color: Color) -> Self {
+        self.cam_3d_bundle.camera_3d.clear_color = ClearColorConfig::Custom(clear_color);
+        self
+    }
 }
 
 /// Marks a camera as the "main" game camera.
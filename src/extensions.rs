@@ -1,9 +1,12 @@
-use std::path::{ Path, PathBuf };
+use std::path::{ Path, PathBuf, Component as PathComponent };
 
 use bevy::prelude::*;
 
 pub trait PathExt {
     fn relativize(&self, parent: impl AsRef<Path>) -> PathBuf;
+
+    /// Resolves "." and ".." components without touching the filesystem
+    fn normalize(&self) -> PathBuf;
 }
 
 impl PathExt for Path {
@@ -31,12 +34,31 @@ impl PathExt for Path {
         }
         result
     }
+
+    fn normalize(&self) -> PathBuf {
+        let mut result = PathBuf::new();
+        for comp in self.components() {
+            match comp {
+                PathComponent::CurDir => {}
+                PathComponent::ParentDir => match result.components().next_back() {
+                    Some(PathComponent::Normal(_)) => { result.pop(); }
+                    _ => result.push(comp)
+                },
+                _ => result.push(comp)
+            }
+        }
+        result
+    }
 }
 
 impl PathExt for PathBuf {
     fn relativize(&self, parent: impl AsRef<Path>) -> PathBuf {
         self.as_path().relativize(parent)
     }
+
+    fn normalize(&self) -> PathBuf {
+        self.as_path().normalize()
+    }
 }
 
 pub trait StandardMaterialExt {
@@ -124,4 +146,12 @@ fn test_relativize() {
     let child = PathBuf::from("parent/path/child/path.txt");
     let relativized = child.relativize(parent);
     assert_eq!(PathBuf::from("").as_path(), &relativized);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_normalize() {
+    let path = PathBuf::from("maps/tmx/../tsx/./collision.tsx");
+    assert_eq!(PathBuf::from("maps/tsx/collision.tsx"), path.normalize());
+    let path = PathBuf::from("../maps/../../tsx");
+    assert_eq!(PathBuf::from("../../tsx"), path.normalize());
+}
@@ -123,7 +123,7 @@ pub enum GameState {
     /// TMX file is being loaded
     MapLoadingFile,
 
-    /// Map collision and/or graphics are being constructed
+    /// Map collision and/or graphics are being constructed on the async compute task pool
     MapConstructing,
 
     /// Map is being spawned
@@ -0,0 +1,774 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy::asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use tiled::{Map, PropertyValue, Properties};
+
+use crate::extensions::PathExt;
+use crate::map::{CurrentMap, CurrentMapGraphics, ChunkKey, Chunk, TileAnimation, TileBounds, TileType, MapObject, MapConfig, MapLoadError, MapLoadErrors, process_tiled_map};
+use crate::physics::{Terrain, TerrainPiece, ChunkCoords};
+
+// Start of every baked map file
+const MAGIC: &[u8; 4] = b"VMAP";
+
+// Version of the format. Bump whenever the layout changes.
+const VERSION: u32 = 3;
+
+// Most terrain pieces a chunk may hold, so that a corrupt file can't request a huge allocation
+const MAX_PIECES_PER_CHUNK: u32 = 1 << 24;
+
+/// Map that was already traversed, stored in a compact binary format ("vmap" files).
+/// Loading one skips parsing the TMX file and running the climber.
+///
+/// Layout, little-endian: magic, version, then the terrain, metadata, object and graphics sections in that order.
+/// Terrain comes first so that it can be read on its own with [`BakedMap::read_terrain`].
+#[derive(Clone, PartialEq, TypeUuid)]
+#[uuid = "0f5b8a5e-2d47-4c8e-b7a1-93d1c4e6f215"]
+pub struct BakedMap {
+    pub terrain: Terrain,
+    /// Tiles the map occupies
+    pub bounds: TileBounds,
+    /// Size of a tile in world space
+    pub tile_size: Vec3,
+    /// Custom properties of the map
+    pub properties: Properties,
+    /// Collision type of every tile with a meta tile, keyed by tile x/y
+    pub meta_tiles: HashMap<IVec2, TileType>,
+    pub objects: Vec<MapObject>,
+    pub graphics: BakedGraphics
+}
+
+/// Graphics of a [`BakedMap`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BakedGraphics {
+    /// Width, height and depth of chunks
+    pub chunk_size: Vec3,
+    /// Image of each tileset, in the same order as the map's tilesets
+    pub tileset_images: Vec<Option<BakedImage>>,
+    /// Chunked mesh data, sorted by key
+    pub chunks: Vec<(ChunkKey, Chunk)>
+}
+
+/// Image of a tileset in a [`BakedMap`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum BakedImage {
+    /// Image file, relative to the asset folder
+    Path(String),
+    /// RGBA8 sRGB pixels, for images that were built at load time such as atlases of image collection tilesets
+    Pixels {
+        width: u32,
+        height: u32,
+        data: Vec<u8>
+    }
+}
+
+impl BakedMap {
+
+    /// Bakes the staging resources of a map that finished constructing.
+    /// Atlases are stored as pixels, so this works for image collection tilesets as well.
+    pub fn from_staged(
+        current_map: &CurrentMap,
+        current_map_graphics: &CurrentMapGraphics,
+        images: &Assets<Image>,
+        asset_server: &AssetServer
+    ) -> Self {
+        let tileset_images = current_map_graphics
+            .tileset_handles
+            .iter()
+            .zip(&current_map_graphics.tileset_atlases)
+            .map(|(handle, atlas)| {
+                let handle = handle.as_ref()?;
+                if atlas.is_some() {
+                    let image = images.get(handle)?;
+                    Some(BakedImage::Pixels {
+                        width: image.texture_descriptor.size.width,
+                        height: image.texture_descriptor.size.height,
+                        data: image.data.clone()
+                    })
+                }
+                else {
+                    let path = asset_server.get_handle_path(handle)?;
+                    Some(BakedImage::Path(path.path().to_string_lossy().into_owned()))
+                }
+            })
+            .collect();
+        Self {
+            terrain: current_map.terrain.clone(),
+            bounds: current_map.bounds,
+            tile_size: current_map.tile_size,
+            properties: current_map.properties.clone(),
+            meta_tiles: current_map.meta_tiles.clone(),
+            objects: current_map.objects.clone(),
+            graphics: BakedGraphics {
+                chunk_size: current_map_graphics.chunk_size,
+                tileset_images,
+                chunks: sorted_chunks(current_map_graphics)
+            }
+        }
+    }
+
+    /// Moves the contents of the baked map into staging resources.
+    /// Image files begin loading through the asset server, and pixels get added to the image assets directly.
+    pub fn stage(
+        &self,
+        current_map: &mut CurrentMap,
+        asset_server: &AssetServer,
+        images: &mut Assets<Image>
+    ) -> CurrentMapGraphics {
+        current_map.terrain = self.terrain.clone();
+        current_map.bounds = self.bounds;
+        current_map.tile_size = self.tile_size;
+        current_map.properties = self.properties.clone();
+        current_map.meta_tiles = self.meta_tiles.clone();
+        current_map.objects = self.objects.clone();
+        let mut current_map_graphics = CurrentMapGraphics::new(self.graphics.chunk_size);
+        for image in &self.graphics.tileset_images {
+            let handle = image.as_ref().map(|image| match image {
+                BakedImage::Path(path) => asset_server.load(path.as_str()),
+                BakedImage::Pixels { width, height, data } => images.add(Image::new(
+                    Extent3d { width: *width, height: *height, depth_or_array_layers: 1 },
+                    TextureDimension::D2,
+                    data.clone(),
+                    TextureFormat::Rgba8UnormSrgb
+                ))
+            });
+            current_map_graphics.tileset_handles.push(handle);
+            current_map_graphics.tileset_atlases.push(None);
+            current_map_graphics.collection_handles.push(Vec::new());
+        }
+        current_map_graphics.chunks = self.graphics.chunks.iter().cloned().collect();
+        current_map_graphics
+    }
+
+    /// Encodes the baked map
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer(Vec::new());
+        writer.0.extend_from_slice(MAGIC);
+        writer.u32(VERSION);
+        write_terrain(&mut writer, &self.terrain);
+        write_metadata(&mut writer, self);
+        write_objects(&mut writer, &self.objects);
+        write_graphics(&mut writer, &self.graphics);
+        writer.0
+    }
+
+    /// Decodes a baked map
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MapLoadError> {
+        let mut reader = Reader::new(bytes)?;
+        let terrain = read_terrain(&mut reader)?;
+        let (bounds, tile_size, properties, meta_tiles) = read_metadata(&mut reader)?;
+        let objects = read_objects(&mut reader)?;
+        let graphics = read_graphics(&mut reader)?;
+        Ok(Self { terrain, bounds, tile_size, properties, meta_tiles, objects, graphics })
+    }
+
+    /// Decodes only the terrain of a baked map.
+    /// Useful when collision is all that is needed, like on a server.
+    pub fn read_terrain(bytes: &[u8]) -> Result<Terrain, MapLoadError> {
+        let mut reader = Reader::new(bytes)?;
+        read_terrain(&mut reader)
+    }
+}
+
+/// Bakes a TMX map without an app running.
+/// Image paths are made relative to the asset folder.
+/// Fails on image collection tilesets, as their atlases only get built at load time. Use [`BakedMap::from_staged`] for those.
+pub fn bake_tiled_map(
+    tiled_map: &Map,
+    config: &MapConfig,
+    asset_folder: impl AsRef<Path>
+) -> Result<BakedMap, MapLoadError> {
+    let mut current_map = CurrentMap::new("", Handle::default());
+    let mut current_map_graphics = CurrentMapGraphics::new(config.chunk_size);
+    let mut tileset_images = Vec::new();
+    for tileset in tiled_map.tilesets() {
+        let image = match &tileset.image {
+            Some(image) => image,
+            None if tileset.tiles().any(|(_, tile)| tile.image.is_some()) => return Err(MapLoadError::Atlas {
+                tileset: tileset.name.clone(),
+                message: "image collection tilesets can only be baked from a loaded map".to_string()
+            }),
+            None => {
+                tileset_images.push(None);
+                current_map_graphics.tileset_atlases.push(None);
+                continue;
+            }
+        };
+        let path = image.source.relativize(asset_folder.as_ref());
+        tileset_images.push(Some(BakedImage::Path(path.to_string_lossy().into_owned())));
+        current_map_graphics.tileset_atlases.push(None);
+    }
+    process_tiled_map(tiled_map, config.flip_y, &mut current_map, &mut current_map_graphics)?;
+    Ok(BakedMap {
+        terrain: current_map.terrain,
+        bounds: current_map.bounds,
+        tile_size: current_map.tile_size,
+        properties: current_map.properties,
+        meta_tiles: current_map.meta_tiles,
+        objects: current_map.objects,
+        graphics: BakedGraphics {
+            chunk_size: current_map_graphics.chunk_size,
+            tileset_images,
+            chunks: sorted_chunks(&current_map_graphics)
+        }
+    })
+}
+
+/// Loads [`BakedMap`]s from "vmap" files
+pub struct BakedMapLoader {
+    errors: MapLoadErrors
+}
+
+impl FromWorld for BakedMapLoader {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            errors: world.get_resource_or_insert_with(MapLoadErrors::default).clone()
+        }
+    }
+}
+
+impl AssetLoader for BakedMapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext
+    ) -> BoxedFuture<'a, anyhow::Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let baked_map = self.errors.record(load_context.path(), BakedMap::from_bytes(bytes))?;
+            load_context.set_default_asset(LoadedAsset::new(baked_map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vmap"]
+    }
+}
+
+// Chunks sorted by key, so that baking the same map twice yields the same bytes
+fn sorted_chunks(current_map_graphics: &CurrentMapGraphics) -> Vec<(ChunkKey, Chunk)> {
+    let mut chunks: Vec<(ChunkKey, Chunk)> = current_map_graphics.chunks
+        .iter()
+        .map(|(key, chunk)| (*key, chunk.clone()))
+        .collect();
+    chunks.sort_by_key(|(key, _)| (key.x, key.y, key.z, key.tileset_handle_index));
+    chunks
+}
+
+// Chunk pieces are run-length encoded, as most of a chunk is usually empty
+fn write_terrain(writer: &mut Writer, terrain: &Terrain) {
+    writer.vec3(terrain.piece_size());
+    let chunk_size = terrain.chunk_size();
+    writer.u32(chunk_size.x);
+    writer.u32(chunk_size.y);
+    writer.u32(chunk_size.z);
+    let mut chunks: Vec<(ChunkCoords, &[TerrainPiece])> = terrain.chunks().collect();
+    chunks.sort_by_key(|(coords, _)| (coords.x, coords.y, coords.z));
+    writer.len(chunks.len());
+    for (coords, pieces) in chunks {
+        writer.i32(coords.x);
+        writer.i32(coords.y);
+        writer.i32(coords.z);
+        let mut runs: Vec<(TerrainPiece, u32)> = Vec::new();
+        for piece in pieces {
+            match runs.last_mut() {
+                Some((run_piece, run_len)) if run_piece == piece => *run_len += 1,
+                _ => runs.push((*piece, 1))
+            }
+        }
+        writer.len(runs.len());
+        for (piece, run_len) in runs {
+            writer.u8(piece.into());
+            writer.u32(run_len);
+        }
+    }
+}
+
+fn read_terrain(reader: &mut Reader) -> Result<Terrain, MapLoadError> {
+    let piece_size = reader.vec3()?;
+    let chunk_size = UVec3::new(reader.u32()?, reader.u32()?, reader.u32()?);
+    if piece_size.cmple(Vec3::ZERO).any() || chunk_size.cmpeq(UVec3::ZERO).any() {
+        return Err(MapLoadError::Baked("invalid terrain dimensions".to_string()));
+    }
+    let pieces_per_chunk = chunk_size.x
+        .checked_mul(chunk_size.y)
+        .and_then(|pieces| pieces.checked_mul(chunk_size.z))
+        .filter(|pieces| *pieces <= MAX_PIECES_PER_CHUNK)
+        .ok_or_else(|| MapLoadError::Baked("terrain chunks are too large".to_string()))? as usize;
+    let mut terrain = Terrain::new(piece_size, chunk_size);
+    for _ in 0..reader.u32()? {
+        let coords = ChunkCoords::new(reader.i32()?, reader.i32()?, reader.i32()?);
+        let mut pieces = Vec::with_capacity(pieces_per_chunk);
+        for _ in 0..reader.u32()? {
+            let value = reader.u8()?;
+            let piece = TerrainPiece::try_from(value)
+                .map_err(|_| MapLoadError::Baked(format!("invalid terrain piece {}", value)))?;
+            let run_len = reader.u32()? as usize;
+            if pieces.len() + run_len > pieces_per_chunk {
+                return Err(MapLoadError::Baked("terrain chunk is too large".to_string()));
+            }
+            pieces.extend(std::iter::repeat(piece).take(run_len));
+        }
+        if pieces.len() != pieces_per_chunk {
+            return Err(MapLoadError::Baked("terrain chunk is too small".to_string()));
+        }
+        terrain.insert_chunk(coords, pieces);
+    }
+    Ok(terrain)
+}
+
+// Meta tiles are sorted by tile, so that baking the same map twice yields the same bytes
+fn write_metadata(writer: &mut Writer, baked_map: &BakedMap) {
+    writer.i32(baked_map.bounds.min.x);
+    writer.i32(baked_map.bounds.min.y);
+    writer.i32(baked_map.bounds.max.x);
+    writer.i32(baked_map.bounds.max.y);
+    writer.vec3(baked_map.tile_size);
+    write_properties(writer, &baked_map.properties);
+    let mut meta_tiles: Vec<(&IVec2, &TileType)> = baked_map.meta_tiles.iter().collect();
+    meta_tiles.sort_by_key(|(tile, _)| (tile.x, tile.y));
+    writer.len(meta_tiles.len());
+    for (tile, tile_type) in meta_tiles {
+        writer.i32(tile.x);
+        writer.i32(tile.y);
+        writer.u8((*tile_type).into());
+    }
+}
+
+fn read_metadata(reader: &mut Reader) -> Result<(TileBounds, Vec3, Properties, HashMap<IVec2, TileType>), MapLoadError> {
+    let min = IVec2::new(reader.i32()?, reader.i32()?);
+    let max = IVec2::new(reader.i32()?, reader.i32()?);
+    let tile_size = reader.vec3()?;
+    let properties = read_properties(reader)?;
+    let mut meta_tiles = HashMap::default();
+    for _ in 0..reader.u32()? {
+        let tile = IVec2::new(reader.i32()?, reader.i32()?);
+        let value = reader.u8()?;
+        let tile_type = TileType::try_from(value)
+            .map_err(|_| MapLoadError::Baked(format!("invalid tile type {}", value)))?;
+        meta_tiles.insert(tile, tile_type);
+    }
+    Ok((TileBounds::new(min, max), tile_size, properties, meta_tiles))
+}
+
+// Colors are stored as ARGB bytes
+fn write_properties(writer: &mut Writer, properties: &Properties) {
+    let mut properties: Vec<(&String, &PropertyValue)> = properties.iter().collect();
+    properties.sort_by_key(|(name, _)| *name);
+    writer.len(properties.len());
+    for (name, value) in properties {
+        writer.str(name);
+        match value {
+            PropertyValue::BoolValue(value) => { writer.u8(0); writer.u8(*value as u8); }
+            PropertyValue::FloatValue(value) => { writer.u8(1); writer.f32(*value); }
+            PropertyValue::IntValue(value) => { writer.u8(2); writer.i32(*value); }
+            PropertyValue::StringValue(value) => { writer.u8(3); writer.str(value); }
+            PropertyValue::FileValue(value) => { writer.u8(4); writer.str(value); }
+            PropertyValue::ColorValue(color) => {
+                writer.u8(5);
+                writer.u8(color.alpha);
+                writer.u8(color.red);
+                writer.u8(color.green);
+                writer.u8(color.blue);
+            }
+            PropertyValue::ObjectValue(value) => { writer.u8(6); writer.u32(*value); }
+        }
+    }
+}
+
+fn read_properties(reader: &mut Reader) -> Result<Properties, MapLoadError> {
+    let mut properties = Properties::new();
+    for _ in 0..reader.u32()? {
+        let name = reader.str()?;
+        let value = match reader.u8()? {
+            0 => PropertyValue::BoolValue(reader.u8()? != 0),
+            1 => PropertyValue::FloatValue(reader.f32()?),
+            2 => PropertyValue::IntValue(reader.i32()?),
+            3 => PropertyValue::StringValue(reader.str()?),
+            4 => PropertyValue::FileValue(reader.str()?),
+            5 => {
+                let [alpha, red, green, blue] = reader.array()?;
+                PropertyValue::ColorValue(tiled::Color { alpha, red, green, blue })
+            }
+            6 => PropertyValue::ObjectValue(reader.u32()?),
+            tag => return Err(MapLoadError::Baked(format!("invalid property type {}", tag)))
+        };
+        properties.insert(name, value);
+    }
+    Ok(properties)
+}
+
+fn write_objects(writer: &mut Writer, objects: &[MapObject]) {
+    writer.len(objects.len());
+    for object in objects {
+        writer.u32(object.id);
+        writer.str(&object.name);
+        writer.str(&object.typ);
+        writer.vec3(object.position);
+        writer.f32(object.size.x);
+        writer.f32(object.size.y);
+        writer.str(&object.layer_name);
+        write_properties(writer, &object.properties);
+    }
+}
+
+fn read_objects(reader: &mut Reader) -> Result<Vec<MapObject>, MapLoadError> {
+    let mut objects = Vec::new();
+    for _ in 0..reader.u32()? {
+        let id = reader.u32()?;
+        let name = reader.str()?;
+        let typ = reader.str()?;
+        let position = reader.vec3()?;
+        let size = Vec2::new(reader.f32()?, reader.f32()?);
+        let layer_name = reader.str()?;
+        let properties = read_properties(reader)?;
+        objects.push(MapObject { id, name, typ, position, size, layer_name, properties });
+    }
+    Ok(objects)
+}
+
+fn write_graphics(writer: &mut Writer, graphics: &BakedGraphics) {
+    writer.vec3(graphics.chunk_size);
+    writer.len(graphics.tileset_images.len());
+    for image in &graphics.tileset_images {
+        match image {
+            None => writer.u8(0),
+            Some(BakedImage::Path(path)) => {
+                writer.u8(1);
+                writer.str(path);
+            }
+            Some(BakedImage::Pixels { width, height, data }) => {
+                writer.u8(2);
+                writer.u32(*width);
+                writer.u32(*height);
+                writer.bytes(data);
+            }
+        }
+    }
+    writer.len(graphics.chunks.len());
+    for (key, chunk) in &graphics.chunks {
+        writer.i32(key.x);
+        writer.i32(key.y);
+        writer.i32(key.z);
+        writer.len(key.tileset_handle_index);
+        writer.floats(chunk.positions.iter().flatten());
+        writer.floats(chunk.normals.iter().flatten());
+        writer.floats(chunk.uvs.iter().flatten());
+        writer.len(chunk.indices.len());
+        for index in &chunk.indices {
+            writer.u32(*index);
+        }
+        writer.len(chunk.animations.len());
+        for animation in &chunk.animations {
+            writer.len(animation.vertex_start);
+            writer.len(animation.frame_uvs.len());
+            for uvs in &animation.frame_uvs {
+                writer.floats(uvs.iter().flatten());
+            }
+            writer.len(animation.frame_durations.len());
+            for duration in &animation.frame_durations {
+                writer.u32(*duration);
+            }
+        }
+    }
+}
+
+fn read_graphics(reader: &mut Reader) -> Result<BakedGraphics, MapLoadError> {
+    let chunk_size = reader.vec3()?;
+    let mut tileset_images = Vec::new();
+    for _ in 0..reader.u32()? {
+        let image = match reader.u8()? {
+            0 => None,
+            1 => Some(BakedImage::Path(reader.str()?)),
+            2 => Some(BakedImage::Pixels {
+                width: reader.u32()?,
+                height: reader.u32()?,
+                data: reader.bytes()?.to_vec()
+            }),
+            tag => return Err(MapLoadError::Baked(format!("invalid image type {}", tag)))
+        };
+        tileset_images.push(image);
+    }
+    let mut chunks = Vec::new();
+    for _ in 0..reader.u32()? {
+        let key = ChunkKey {
+            x: reader.i32()?,
+            y: reader.i32()?,
+            z: reader.i32()?,
+            tileset_handle_index: reader.u32()? as usize
+        };
+        if key.tileset_handle_index >= tileset_images.len() {
+            return Err(MapLoadError::Baked(format!("invalid tileset index {}", key.tileset_handle_index)));
+        }
+        let positions = reader.vec3s()?;
+        let normals = reader.vec3s()?;
+        let uvs = reader.vec2s()?;
+        let mut indices = Vec::new();
+        for _ in 0..reader.u32()? {
+            indices.push(reader.u32()?);
+        }
+        let mut animations = Vec::new();
+        for _ in 0..reader.u32()? {
+            let vertex_start = reader.u32()? as usize;
+            let mut frame_uvs = Vec::new();
+            for _ in 0..reader.u32()? {
+                frame_uvs.push(reader.vec2s()?);
+            }
+            let mut frame_durations = Vec::new();
+            for _ in 0..reader.u32()? {
+                frame_durations.push(reader.u32()?);
+            }
+            animations.push(TileAnimation { vertex_start, frame_uvs, frame_durations });
+        }
+        let chunk = Chunk { positions, normals, uvs, indices, animations };
+        validate_chunk(&chunk)?;
+        chunks.push((key, chunk));
+    }
+    Ok(BakedGraphics { chunk_size, tileset_images, chunks })
+}
+
+// Checks that a chunk can be turned into a mesh and animated without going out of bounds
+fn validate_chunk(chunk: &Chunk) -> Result<(), MapLoadError> {
+    let vertex_count = chunk.positions.len();
+    if chunk.normals.len() != vertex_count || chunk.uvs.len() != vertex_count {
+        return Err(MapLoadError::Baked("chunk vertex attributes differ in length".to_string()));
+    }
+    if chunk.indices.iter().any(|index| *index as usize >= vertex_count) {
+        return Err(MapLoadError::Baked("chunk index out of range".to_string()));
+    }
+    for animation in &chunk.animations {
+        if animation.frame_uvs.is_empty() || animation.frame_uvs.len() != animation.frame_durations.len() {
+            return Err(MapLoadError::Baked("animation frame count mismatch".to_string()));
+        }
+        let fits = animation.frame_uvs
+            .iter()
+            .all(|uvs| animation.vertex_start.checked_add(uvs.len()).map_or(false, |end| end <= vertex_count));
+        if !fits {
+            return Err(MapLoadError::Baked("animation out of range".to_string()));
+        }
+    }
+    Ok(())
+}
+
+// Appends little-endian values to a buffer
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) { self.0.push(value); }
+    fn u32(&mut self, value: u32) { self.0.extend_from_slice(&value.to_le_bytes()); }
+    fn i32(&mut self, value: i32) { self.0.extend_from_slice(&value.to_le_bytes()); }
+    fn f32(&mut self, value: f32) { self.0.extend_from_slice(&value.to_le_bytes()); }
+    fn len(&mut self, len: usize) { self.u32(len as u32); }
+
+    fn vec3(&mut self, value: Vec3) {
+        self.f32(value.x);
+        self.f32(value.y);
+        self.f32(value.z);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    fn floats<'a>(&mut self, values: impl ExactSizeIterator<Item=&'a f32>) {
+        self.len(values.len());
+        for value in values {
+            self.f32(*value);
+        }
+    }
+}
+
+// Reads little-endian values from a buffer, failing if it runs out
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+
+    // Reader positioned after the header
+    fn new(bytes: &'a [u8]) -> Result<Self, MapLoadError> {
+        let mut reader = Self(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(MapLoadError::Baked("not a baked map".to_string()));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(MapLoadError::Baked(format!("unsupported version {}", version)));
+        }
+        Ok(reader)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MapLoadError> {
+        if len > self.0.len() {
+            return Err(MapLoadError::Baked("unexpected end of file".to_string()));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], MapLoadError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, MapLoadError> { Ok(self.take(1)?[0]) }
+    fn u32(&mut self) -> Result<u32, MapLoadError> { Ok(u32::from_le_bytes(self.array()?)) }
+    fn i32(&mut self) -> Result<i32, MapLoadError> { Ok(i32::from_le_bytes(self.array()?)) }
+    fn f32(&mut self) -> Result<f32, MapLoadError> { Ok(f32::from_le_bytes(self.array()?)) }
+
+    fn vec3(&mut self) -> Result<Vec3, MapLoadError> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], MapLoadError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn str(&mut self) -> Result<String, MapLoadError> {
+        String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|_| MapLoadError::Baked("invalid string".to_string()))
+    }
+
+    fn floats(&mut self) -> Result<Vec<f32>, MapLoadError> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len.checked_mul(4).unwrap_or(usize::MAX))?;
+        Ok(bytes.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect())
+    }
+
+    fn vec3s(&mut self) -> Result<Vec<[f32; 3]>, MapLoadError> {
+        let floats = self.floats()?;
+        if floats.len() % 3 != 0 {
+            return Err(MapLoadError::Baked("incomplete vertex attribute".to_string()));
+        }
+        Ok(floats.chunks_exact(3).map(|v| [v[0], v[1], v[2]]).collect())
+    }
+
+    fn vec2s(&mut self) -> Result<Vec<[f32; 2]>, MapLoadError> {
+        let floats = self.floats()?;
+        if floats.len() % 2 != 0 {
+            return Err(MapLoadError::Baked("incomplete vertex attribute".to_string()));
+        }
+        Ok(floats.chunks_exact(2).map(|v| [v[0], v[1]]).collect())
+    }
+}
+
+
+#[test]
+fn test_baked_map_roundtrip() {
+    use crate::physics::Coords;
+
+    let mut terrain = Terrain::new(Vec3::new(16.0, 16.0, 16.0), UVec3::new(4, 4, 4));
+    *terrain.get_or_create_mut(Coords::new(0, 0, 0)) = TerrainPiece::Cuboid;
+    *terrain.get_or_create_mut(Coords::new(1, 0, 0)) = TerrainPiece::Stairs;
+    *terrain.get_or_create_mut(Coords::new(-3, 5, 2)) = TerrainPiece::SlopeW;
+
+    let mut properties = Properties::new();
+    properties.insert("locked".to_string(), PropertyValue::BoolValue(true));
+    properties.insert("gold".to_string(), PropertyValue::IntValue(-12));
+    properties.insert("message".to_string(), PropertyValue::StringValue("Hello".to_string()));
+    properties.insert("clear_color".to_string(), PropertyValue::ColorValue(tiled::Color { alpha: 255, red: 32, green: 64, blue: 128 }));
+    properties.insert("target".to_string(), PropertyValue::ObjectValue(3));
+    let object = MapObject {
+        id: 7,
+        name: "chest".to_string(),
+        typ: "Chest".to_string(),
+        position: Vec3::new(32.0, 16.0, -48.0),
+        size: Vec2::new(16.0, 16.0),
+        layer_name: "group/objects".to_string(),
+        properties
+    };
+
+    let chunk = Chunk {
+        positions: vec![[0.0, 0.0, 0.0], [16.0, 0.0, 0.0], [16.0, 16.0, 0.0]],
+        normals: vec![[0.0, 0.0, 1.0]; 3],
+        uvs: vec![[0.0, 1.0], [0.5, 1.0], [0.5, 0.5]],
+        indices: vec![0, 1, 2],
+        animations: vec![TileAnimation {
+            vertex_start: 0,
+            frame_uvs: vec![vec![[0.0, 1.0]; 3], vec![[0.5, 1.0]; 3]],
+            frame_durations: vec![100, 250]
+        }]
+    };
+    let mut meta_tiles = HashMap::default();
+    meta_tiles.insert(IVec2::new(3, -2), TileType::SlopeStartW);
+    let baked_map = BakedMap {
+        terrain,
+        bounds: TileBounds::new(IVec2::new(0, -16), IVec2::new(32, 16)),
+        tile_size: Vec3::new(16.0, 16.0, 16.0),
+        properties: object.properties.clone(),
+        meta_tiles,
+        objects: vec![object],
+        graphics: BakedGraphics {
+            chunk_size: Vec3::new(256.0, 256.0, 256.0),
+            tileset_images: vec![
+                Some(BakedImage::Path("images/tileset.png".to_string())),
+                None,
+                Some(BakedImage::Pixels { width: 1, height: 1, data: vec![255, 0, 255, 255] })
+            ],
+            chunks: vec![(ChunkKey { x: 0, y: -1, z: 2, tileset_handle_index: 2 }, chunk)]
+        }
+    };
+
+    let bytes = baked_map.to_bytes();
+    assert!(BakedMap::from_bytes(&bytes).unwrap() == baked_map);
+    assert!(BakedMap::read_terrain(&bytes).unwrap() == baked_map.terrain);
+    assert!(BakedMap::from_bytes(&bytes[..bytes.len()-1]).is_err());
+    assert!(BakedMap::from_bytes(b"TMX!").is_err());
+}
+
+#[test]
+fn test_baked_map_rejects_corrupt_input() {
+    let bad_chunk = |chunk: Chunk| {
+        let baked_map = BakedMap {
+            terrain: Terrain::new(Vec3::new(16.0, 16.0, 16.0), UVec3::new(4, 4, 4)),
+            bounds: TileBounds::default(),
+            tile_size: Vec3::new(16.0, 16.0, 16.0),
+            properties: Properties::new(),
+            meta_tiles: HashMap::default(),
+            objects: Vec::new(),
+            graphics: BakedGraphics {
+                chunk_size: Vec3::new(256.0, 256.0, 256.0),
+                tileset_images: vec![None],
+                chunks: vec![(ChunkKey { x: 0, y: 0, z: 0, tileset_handle_index: 0 }, chunk)]
+            }
+        };
+        BakedMap::from_bytes(&baked_map.to_bytes()).is_err()
+    };
+    let chunk = Chunk {
+        positions: vec![[0.0, 0.0, 0.0], [16.0, 0.0, 0.0], [16.0, 16.0, 0.0]],
+        normals: vec![[0.0, 0.0, 1.0]; 3],
+        uvs: vec![[0.0, 1.0]; 3],
+        indices: vec![0, 1, 2],
+        animations: Vec::new()
+    };
+    assert!(!bad_chunk(chunk.clone()));
+    assert!(bad_chunk(Chunk { normals: vec![[0.0, 0.0, 1.0]; 2], ..chunk.clone() }));
+    assert!(bad_chunk(Chunk { indices: vec![0, 1, 3], ..chunk.clone() }));
+    assert!(bad_chunk(Chunk {
+        animations: vec![TileAnimation { vertex_start: 1, frame_uvs: vec![vec![[0.0, 1.0]; 3]], frame_durations: vec![100] }],
+        ..chunk.clone()
+    }));
+    assert!(bad_chunk(Chunk {
+        animations: vec![TileAnimation { vertex_start: 0, frame_uvs: vec![vec![[0.0, 1.0]; 3]], frame_durations: Vec::new() }],
+        ..chunk
+    }));
+
+    // Terrain chunks whose piece count overflows
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    for value in [16.0_f32, 16.0, 16.0] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [u32::MAX, u32::MAX, 2] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.extend_from_slice(&1_u32.to_le_bytes());
+    assert!(BakedMap::read_terrain(&bytes).is_err());
+}
@@ -0,0 +1,231 @@
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::render_resource::PrimitiveTopology;
+use bevy::utils::HashMap;
+
+use crate::camera::MainCamera;
+use crate::map::{AnimatedChunk, Chunk, ChunkKey, CurrentMapGraphics, MapChunk, MapConfig, MapEntity, TilesetAtlas};
+use crate::physics::{CylinderShape, Position, Terrain};
+
+/// Resource holding every chunk of the spawned map.
+/// Only chunk meshes near the [`MainCamera`] are spawned as entities, and only terrain chunks near physics entities
+/// are part of the spawned [`Terrain`]. The rest wait here until something comes close enough.
+pub struct StreamedMap {
+    pub owner: MapEntity,
+    /// Width, height and depth of chunk meshes
+    pub chunk_size: Vec3,
+    pub tileset_handles: Vec<Option<Handle<Image>>>,
+    pub tileset_atlases: Vec<Option<TilesetAtlas>>,
+    /// Mesh data of every chunk, spawned or not
+    pub chunks: HashMap<ChunkKey, Chunk>,
+    /// Terrain of the whole map
+    pub terrain: Terrain,
+    spawned_chunks: HashMap<ChunkKey, Entity>,
+    materials: Vec<Option<Handle<StandardMaterial>>>
+}
+
+impl StreamedMap {
+
+    /// Takes the chunks and terrain of a map that is spawning
+    pub fn new(owner: MapEntity, current_map_graphics: CurrentMapGraphics, terrain: Terrain) -> Self {
+        let materials = vec![None; current_map_graphics.tileset_handles.len()];
+        Self {
+            owner,
+            chunk_size: current_map_graphics.chunk_size,
+            tileset_handles: current_map_graphics.tileset_handles,
+            tileset_atlases: current_map_graphics.tileset_atlases,
+            chunks: current_map_graphics.chunks,
+            terrain,
+            spawned_chunks: HashMap::default(),
+            materials
+        }
+    }
+
+    /// Entity of a chunk mesh, if it is spawned
+    pub fn chunk_entity(&self, key: ChunkKey) -> Option<Entity> {
+        self.spawned_chunks.get(&key).copied()
+    }
+
+    // Replaces the mesh data of every chunk that matches, respawning those that were spawned.
+    // Matching chunks that weren't spawned are left for streaming to pick up.
+    pub(crate) fn replace_chunks(
+        &mut self,
+        matches: impl Fn(ChunkKey) -> bool,
+        chunks: HashMap<ChunkKey, Chunk>,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<StandardMaterial>,
+        commands: &mut Commands
+    ) {
+        let respawned: Vec<ChunkKey> = self.spawned_chunks
+            .keys()
+            .copied()
+            .filter(|key| matches(*key))
+            .collect();
+        for key in &respawned {
+            if let Some(entity) = self.spawned_chunks.remove(key) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        self.chunks.retain(|key, _| !matches(*key));
+        self.chunks.extend(chunks.into_iter().filter(|(key, _)| matches(*key)));
+        for key in respawned {
+            if let Some(entity) = self.spawn_chunk(key, meshes, materials, commands) {
+                self.spawned_chunks.insert(key, entity);
+            }
+        }
+    }
+
+    // Spawns the mesh of a chunk as a PbrBundle.
+    // None if the chunk's tileset has no image.
+    fn spawn_chunk(
+        &mut self,
+        key: ChunkKey,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<StandardMaterial>,
+        commands: &mut Commands
+    ) -> Option<Entity> {
+        let chunk = self.chunks.get(&key)?;
+        let image_handle = self.tileset_handles[key.tileset_handle_index].as_ref()?;
+
+        // Creates mesh for chunk
+        let chunk_pos = Vec3::new(key.x as f32, key.y as f32, key.z as f32) * self.chunk_size;
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, chunk.positions.clone());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, chunk.normals.clone());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, chunk.uvs.clone());
+        mesh.set_indices(Some(Indices::U32(chunk.indices.clone())));
+        let mesh_handle = meshes.add(mesh);
+
+        // Chunks of the same tileset share a material
+        let material_handle = self.materials[key.tileset_handle_index]
+            .get_or_insert_with(|| materials.add(StandardMaterial {
+                base_color_texture: Some(image_handle.clone()),
+                metallic: 0.0,
+                reflectance: 0.0,
+                perceptual_roughness: 1.0,
+                alpha_mode: AlphaMode::Mask(0.5),
+                ..Default::default()
+            }))
+            .clone();
+
+        // Creates entity for chunk
+        let mut chunk_entity = commands.spawn_bundle(PbrBundle {
+            mesh: mesh_handle.clone(),
+            material: material_handle,
+            transform: Transform::from_translation(chunk_pos),
+            ..Default::default()
+        });
+        chunk_entity.insert(MapChunk).insert(self.owner.clone());
+        if !chunk.animations.is_empty() {
+            chunk_entity.insert(AnimatedChunk::new(mesh_handle, chunk.animations.clone()));
+        }
+        Some(chunk_entity.id())
+    }
+}
+
+/// Spawns chunk meshes that come within [`MapConfig::chunk_load_radius`] of the main camera,
+/// and despawns those that go beyond [`MapConfig::chunk_unload_radius`].
+pub(crate) fn stream_map_chunks(
+    streamed_map: Option<ResMut<StreamedMap>>,
+    map_config: Res<MapConfig>,
+    camera: Query<&Position, With<MainCamera>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands
+) {
+    log::debug!("(SYSTEM) stream_map_chunks");
+
+    let (mut streamed_map, camera_pos) = match (streamed_map, camera.iter().next()) {
+        (Some(streamed_map), Some(camera_pos)) => (streamed_map, camera_pos.0),
+        _ => return
+    };
+
+    // Sorts chunks into those that should be spawned and those that should be despawned
+    let chunk_size = streamed_map.chunk_size;
+    let mut to_spawn = Vec::new();
+    let mut to_despawn = Vec::new();
+    for key in streamed_map.chunks.keys() {
+        let min = Vec3::new(key.x as f32, key.y as f32, key.z as f32) * chunk_size;
+        let distance = distance_to_box(camera_pos, min, min + chunk_size);
+        let spawned = streamed_map.spawned_chunks.contains_key(key);
+        if !spawned && distance <= map_config.chunk_load_radius {
+            to_spawn.push(*key);
+        }
+        else if spawned && distance > map_config.chunk_unload_radius {
+            to_despawn.push(*key);
+        }
+    }
+
+    for key in to_despawn {
+        if let Some(entity) = streamed_map.spawned_chunks.remove(&key) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    for key in to_spawn {
+        if let Some(entity) = streamed_map.spawn_chunk(key, &mut meshes, &mut materials, &mut commands) {
+            streamed_map.spawned_chunks.insert(key, entity);
+        }
+    }
+}
+
+/// Copies terrain chunks that come within [`MapConfig::terrain_load_radius`] of a physics entity into the spawned terrain,
+/// and removes those that are beyond [`MapConfig::terrain_unload_radius`] of every physics entity.
+pub(crate) fn stream_terrain_chunks(
+    streamed_map: Option<Res<StreamedMap>>,
+    map_config: Res<MapConfig>,
+    bodies: Query<&Position, With<CylinderShape>>,
+    mut terrain_query: Query<&mut Terrain, With<MapEntity>>
+) {
+    log::debug!("(SYSTEM) stream_terrain_chunks");
+
+    let (streamed_map, mut terrain) = match (streamed_map, terrain_query.iter_mut().next()) {
+        (Some(streamed_map), Some(terrain)) => (streamed_map, terrain),
+        _ => return
+    };
+
+    // Sorts chunks into those that should be loaded and those that should be unloaded
+    let source = &streamed_map.terrain;
+    let chunk_size = source.chunk_size().as_vec3() * source.piece_size();
+    let mut to_load = Vec::new();
+    let mut to_unload = Vec::new();
+    for (coords, pieces) in source.chunks() {
+        let min = Vec3::new(coords.x as f32, coords.y as f32, coords.z as f32) * chunk_size;
+        let max = min + chunk_size;
+        let distance = bodies
+            .iter()
+            .map(|position| distance_to_box(position.0, min, max))
+            .fold(f32::INFINITY, f32::min);
+        let loaded = terrain.contains_chunk(coords);
+        if !loaded && distance <= map_config.terrain_load_radius {
+            to_load.push((coords, pieces));
+        }
+        else if loaded && distance > map_config.terrain_unload_radius {
+            to_unload.push(coords);
+        }
+    }
+
+    // Only touches the terrain when something changed, so that change detection stays meaningful
+    if to_load.is_empty() && to_unload.is_empty() {
+        return;
+    }
+    for coords in to_unload {
+        terrain.remove_chunk(coords);
+    }
+    for (coords, pieces) in to_load {
+        terrain.insert_chunk(coords, pieces.to_vec());
+    }
+}
+
+// Distance from a point to the closest point of a box. 0 if the point is inside.
+fn distance_to_box(point: Vec3, min: Vec3, max: Vec3) -> f32 {
+    point.distance(point.clamp(min, max))
+}
+
+
+#[test]
+fn test_distance_to_box() {
+    let (min, max) = (Vec3::ZERO, Vec3::new(256.0, 256.0, 256.0));
+    assert_eq!(0.0, distance_to_box(Vec3::new(128.0, 0.0, 64.0), min, max));
+    assert_eq!(44.0, distance_to_box(Vec3::new(300.0, 100.0, 100.0), min, max));
+    assert_eq!(5.0, distance_to_box(Vec3::new(-3.0, -4.0, 0.0), min, max));
+}
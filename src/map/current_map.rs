@@ -1,19 +1,48 @@
-use crate::map::VidyaMap;
+use crate::map::{VidyaMap, VidyaWorld, BakedMap, MapObject, TileBounds, TileType};
 use crate::physics::{Terrain, TerrainPiece, Coords};
 
 use bevy::prelude::*;
+use bevy::utils::HashMap;
+use tiled::Properties;
 
 
 // Temporary staging resource for a map's collision data / metadata.
 #[derive(Clone)]
 pub struct CurrentMap {
     pub name: String,
-    pub map_handle: Handle<VidyaMap>,   // Map handle
-    pub terrain: Terrain                // Terrain of the current map
+    pub map_handle: Handle<VidyaMap>,               // Map handle
+    pub terrain: Terrain,                           // Terrain of the current map
+    pub objects: Vec<MapObject>,                    // Objects placed in the current map
+    pub bounds: TileBounds,                         // Tiles the current map occupies
+    pub tile_size: Vec3,                            // Size of a tile in world space
+    pub properties: Properties,                     // Custom properties of the current map
+    pub meta_tiles: HashMap<IVec2, TileType>,       // Collision type of every tile with a meta tile, keyed by tile x/y
+    pub meta_overrides: HashMap<IVec2, TileType>,   // Types to use instead of what the map file says, keyed by tile x/y
+    pub reloading: bool,                            // True if replacing the spawned map after its files changed
+    pub baked_handle: Option<Handle<BakedMap>>,     // Baked map to load instead of the TMX map, if any
+    pub world_handle: Option<Handle<VidyaWorld>>    // World whose maps to load instead of the TMX map, if any
 }
 
 impl CurrentMap {
 
+    /// Current map with no terrain or objects
+    pub fn new(name: impl Into<String>, map_handle: Handle<VidyaMap>) -> Self {
+        Self {
+            name: name.into(),
+            map_handle,
+            terrain: Terrain::new(Vec3::new(16.0, 16.0, 16.0), UVec3::new(16, 16, 16)),
+            objects: Vec::new(),
+            bounds: TileBounds::default(),
+            tile_size: Vec3::new(16.0, 16.0, 16.0),
+            properties: Properties::new(),
+            meta_tiles: HashMap::default(),
+            meta_overrides: HashMap::default(),
+            reloading: false,
+            baked_handle: None,
+            world_handle: None
+        }
+    }
+
     /// Sets the terrain piece at the specified coordinates
     pub fn set_terrain_piece(&mut self, piece: TerrainPiece, coords: Coords) {
         let current_piece_ref = self.terrain.get_or_create_mut(coords);
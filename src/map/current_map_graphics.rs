@@ -1,16 +1,33 @@
 use std::f32::consts::SQRT_2;
 
 use bevy::{prelude::*, asset::LoadState};
+use bevy::sprite::Rect;
 use bevy::utils::HashMap;
+use tiled::Tileset;
 
-use crate::map::{ TileGraphics, TileShape, TileMeshData };
+use crate::map::{ TileGraphics, TileShape, TileFrame, TileAnimation, LocalId, MapLoadError };
+use crate::physics::LIP_HEIGHT;
+
+// Maximum width/height of an atlas built from an image collection tileset
+const MAX_ATLAS_SIZE: f32 = 4096.0;
 
 // Temporary staging resource for a map's graphics data.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct CurrentMapGraphics {
-    pub tileset_handles: Vec<Option<Handle<Image>>>,    // Image handle list
-    pub chunk_size: Vec3,                               // Width, height and depth of chunks
-    pub chunks: HashMap<ChunkKey, Chunk>                // Chunked mesh data
+    pub tileset_handles: Vec<Option<Handle<Image>>>,                // Image handle list
+    pub tileset_atlases: Vec<Option<TilesetAtlas>>,                 // Atlases of image collection tilesets
+    pub collection_handles: Vec<Vec<(LocalId, Handle<Image>)>>,     // Images of image collection tilesets, waiting to be packed into atlases
+    pub chunk_size: Vec3,                                           // Width, height and depth of chunks
+    pub chunks: HashMap<ChunkKey, Chunk>                            // Chunked mesh data
+}
+
+/// Atlas that the images of an image collection tileset were packed into
+#[derive(Debug, Clone, Default)]
+pub struct TilesetAtlas {
+    /// Size of the atlas image in pixels
+    pub size: Vec2,
+    /// Region of the atlas image each tile occupies
+    pub rects: HashMap<LocalId, Rect>
 }
 
 impl CurrentMapGraphics {
@@ -22,18 +39,68 @@ impl CurrentMapGraphics {
         }
     }
 
+    /// Load state of the tileset images, and of the images of image collection tilesets.
+    /// Only meaningful before [`CurrentMapGraphics::build_atlases`] is called, as atlases are not loaded through the asset server.
     pub fn get_load_state(&self, assets: &AssetServer) -> LoadState {
-        let handle_ids = self
+        let tileset_ids = self
             .tileset_handles
             .iter()
             .flatten()
             .map(|handle| { handle.id });
-        assets.get_group_load_state(handle_ids)
+        let collection_ids = self
+            .collection_handles
+            .iter()
+            .flatten()
+            .map(|(_, handle)| { handle.id });
+        assets.get_group_load_state(tileset_ids.chain(collection_ids))
     }
 
-    pub fn add_tile(&mut self, mut tile: TileGraphics) {
+    /// Packs the images of every image collection tileset into an atlas, and uses that atlas as the tileset's image
+    pub fn build_atlases(&mut self, tilesets: &[std::sync::Arc<Tileset>], images: &mut Assets<Image>) -> Result<(), MapLoadError> {
+        let collection_handles = std::mem::take(&mut self.collection_handles);
+        for (tileset_index, tile_handles) in collection_handles.into_iter().enumerate() {
+            if tile_handles.is_empty() {
+                continue;
+            }
+
+            // Packs images
+            let mut builder = TextureAtlasBuilder::default().max_size(Vec2::new(MAX_ATLAS_SIZE, MAX_ATLAS_SIZE));
+            for (_, handle) in &tile_handles {
+                let image = images.get(handle).unwrap();
+                builder.add_texture(handle.clone(), image);
+            }
+            let atlas = builder.finish(images).map_err(|err| MapLoadError::Atlas {
+                tileset: tilesets[tileset_index].name.clone(),
+                message: err.to_string()
+            })?;
+
+            // Remembers where each tile ended up
+            let rects = tile_handles
+                .iter()
+                .map(|(tile_id, handle)| {
+                    let index = atlas.get_texture_index(handle).unwrap();
+                    (*tile_id, atlas.textures[index])
+                })
+                .collect();
+            self.tileset_handles[tileset_index] = Some(atlas.texture.clone());
+            self.tileset_atlases[tileset_index] = Some(TilesetAtlas {
+                size: atlas.size,
+                rects
+            });
+        }
+        Ok(())
+    }
+
+    pub fn add_tile(&mut self, tile: TileGraphics) {
+        self.add_animated_tile(tile, &[]);
+    }
+
+    /// Adds a tile whose UVs cycle through the frames specified.
+    /// Behaves like [`CurrentMapGraphics::add_tile`] if there are no frames.
+    pub fn add_animated_tile(&mut self, mut tile: TileGraphics, frames: &[TileFrame]) {
         let chunk_size = self.chunk_size;
         let chunk_coords = tile.translation / chunk_size;
+        let chunk_coords = chunk_coords.floor();
         let (cx, cy, cz) = (chunk_coords.x as i32, chunk_coords.y as i32, chunk_coords.z as i32);
         let tileset_index = tile.tileset_index as usize;
         let key = ChunkKey { x: cx, y: cy, z: cz, tileset_handle_index: tileset_index };
@@ -41,7 +108,11 @@ impl CurrentMapGraphics {
         
         let chunk_offset = Vec3::new(cx as f32, cy as f32, cz as f32) * chunk_size;
         tile.translation -= chunk_offset;
+        let vertex_start = chunk.uvs.len();
         chunk.add_tile(tile);
+        if !frames.is_empty() {
+            chunk.add_animation(tile, vertex_start, frames);
+        }
         log::trace!("Added tile {:?} at pos {:?} to {:?}", tile.shape, tile.translation, key);
     }
 }
@@ -59,11 +130,29 @@ pub struct Chunk {
     pub positions: Vec<[f32; 3]>,
     pub normals: Vec<[f32; 3]>,
     pub uvs: Vec<[f32; 2]>,
-    pub indices: Vec<u32>
+    pub indices: Vec<u32>,
+    pub animations: Vec<TileAnimation>
 }
 
 impl Chunk {
 
+    // Records the UVs of every frame of a tile that was just added
+    fn add_animation(&mut self, tile: TileGraphics, vertex_start: usize, frames: &[TileFrame]) {
+        let frame_uvs = frames
+            .iter()
+            .map(|frame| {
+                let mut frame_chunk = Chunk::default();
+                frame_chunk.add_tile(TileGraphics { mesh_data: frame.mesh_data, ..tile });
+                frame_chunk.uvs
+            })
+            .collect();
+        self.animations.push(TileAnimation {
+            vertex_start,
+            frame_uvs,
+            frame_durations: frames.iter().map(|frame| frame.duration).collect()
+        });
+    }
+
     fn add_tile(&mut self, tile: TileGraphics) {
 
         const X: usize = 0;
@@ -82,6 +171,8 @@ impl Chunk {
         // Gets tile's mesh data
         let md = tile.mesh_data;
         let (tw, th) = (md.size.x, md.size.y);
+        let tile_uvs = md.uvs();
+        let [uv1, uv2, uv3, uv4] = tile_uvs;
         let vlen = p.len() as u32;
 
         // Writes tile to buffers
@@ -99,7 +190,7 @@ impl Chunk {
                 let norm = [0.0, 1.0, 0.0];
                 for _ in 0..4 { n.push(norm); }
                 // UVs and indices
-                push_uv_indices(&md, uvs, i, vlen);
+                push_uv_indices(&tile_uvs, uvs, i, vlen);
             },
             TileShape::Wall => {
                 // Positions (4)
@@ -114,7 +205,7 @@ impl Chunk {
                 let norm = [0.0, 0.0, 1.0];
                 for _ in 0..4 { n.push(norm); }
                 // UVs and indices
-                push_uv_indices(&md, uvs, i, vlen);
+                push_uv_indices(&tile_uvs, uvs, i, vlen);
             },
             TileShape::WallStartSE => {
                 // Vertices (6)
@@ -142,12 +233,12 @@ impl Chunk {
                 n.push(se);
 
                 // UVs and indices
-                uvs.push(md.uv1.to_array());
-                uvs.push(md.uv2.to_array());
-                uvs.push(md.uv3.to_array());
-                uvs.push(md.uv3.to_array());
-                uvs.push(md.uv4.to_array());
-                uvs.push(md.uv1.to_array());
+                uvs.push(uv1.to_array());
+                uvs.push(uv2.to_array());
+                uvs.push(uv3.to_array());
+                uvs.push(uv3.to_array());
+                uvs.push(uv4.to_array());
+                uvs.push(uv1.to_array());
 
                 // Indices
                 i.push(vlen);
@@ -184,12 +275,12 @@ impl Chunk {
                 n.push(sw);
 
                 // UVs
-                uvs.push(md.uv4.to_array());
-                uvs.push(md.uv1.to_array());
-                uvs.push(md.uv2.to_array());
-                uvs.push(md.uv2.to_array());
-                uvs.push(md.uv3.to_array());
-                uvs.push(md.uv4.to_array());
+                uvs.push(uv4.to_array());
+                uvs.push(uv1.to_array());
+                uvs.push(uv2.to_array());
+                uvs.push(uv2.to_array());
+                uvs.push(uv3.to_array());
+                uvs.push(uv4.to_array());
 
                 // Indices
                 i.push(vlen);
@@ -218,7 +309,7 @@ impl Chunk {
                     n.push(norm);
                 }
                 // UVs and indices
-                push_uv_indices(&md, uvs, i, vlen);
+                push_uv_indices(&tile_uvs, uvs, i, vlen);
             }
             TileShape::WallSW => {
                 // Vertices (4)
@@ -242,7 +333,7 @@ impl Chunk {
                     n.push(norm);
                 }
                 // UVs and indices
-                push_uv_indices(&md, uvs, i, vlen);
+                push_uv_indices(&tile_uvs, uvs, i, vlen);
             }
             TileShape::WallEndSE => {
 
@@ -271,12 +362,12 @@ impl Chunk {
                 n.push(up);
 
                 // UVs
-                uvs.push(md.uv1.to_array());
-                uvs.push(md.uv2.to_array());
-                uvs.push(md.uv3.to_array());
-                uvs.push(md.uv3.to_array());
-                uvs.push(md.uv4.to_array());
-                uvs.push(md.uv1.to_array());
+                uvs.push(uv1.to_array());
+                uvs.push(uv2.to_array());
+                uvs.push(uv3.to_array());
+                uvs.push(uv3.to_array());
+                uvs.push(uv4.to_array());
+                uvs.push(uv1.to_array());
 
                 // Indices
                 i.push(vlen);
@@ -313,12 +404,12 @@ impl Chunk {
                 n.push(up);
 
                 // UVs
-                uvs.push(md.uv4.to_array());
-                uvs.push(md.uv1.to_array());
-                uvs.push(md.uv2.to_array());
-                uvs.push(md.uv2.to_array());
-                uvs.push(md.uv3.to_array());
-                uvs.push(md.uv4.to_array());
+                uvs.push(uv4.to_array());
+                uvs.push(uv1.to_array());
+                uvs.push(uv2.to_array());
+                uvs.push(uv2.to_array());
+                uvs.push(uv3.to_array());
+                uvs.push(uv4.to_array());
 
                 // Indices
                 i.push(vlen);
@@ -342,26 +433,226 @@ impl Chunk {
                 let norm = [0.0, 1.0, 0.0];
                 for _ in 0..4 { n.push(norm); }
                 // UVs and indices
-                push_uv_indices(&md, uvs, i, vlen);
+                push_uv_indices(&tile_uvs, uvs, i, vlen);
             },
-            _ => {
-                //panic!("Unsupported tile shape '{:?}'", tile.shape);
+            TileShape::LipN | TileShape::LipNE | TileShape::LipNW => {
+                // Top of the cliff occupies the bottom of the tile, and the rim stands up from its far edge.
+                // Both take up as much of the screen as their part of the tile does.
+                let top_depth = th * (1.0 - LIP_HEIGHT);
+                let rim_height = th * LIP_HEIGHT;
+
+                // Positions (8)
+                p.push(tp);
+                tp[X] += tw;
+                p.push(tp);
+                tp[Z] -= top_depth;
+                p.push(tp);
+                tp[X] -= tw;
+                p.push(tp);
+                p.push(tp);
+                tp[X] += tw;
+                p.push(tp);
+                tp[Y] += rim_height;
+                p.push(tp);
+                tp[X] -= tw;
+                p.push(tp);
+
+                // Normals (8)
+                let up = [0.0, 1.0, 0.0];
+                let rim = match tile.shape {
+                    TileShape::LipNE => [1.0/SQRT_2, 0.0, 1.0/SQRT_2],
+                    TileShape::LipNW => [-1.0/SQRT_2, 0.0, 1.0/SQRT_2],
+                    _ => [0.0, 0.0, 1.0]
+                };
+                for _ in 0..4 { n.push(up); }
+                for _ in 0..4 { n.push(rim); }
+
+                // UVs and indices
+                let split = 1.0 - LIP_HEIGHT;
+                push_uv_indices(&uv_band(&tile_uvs, 0.0, split), uvs, i, vlen);
+                push_uv_indices(&uv_band(&tile_uvs, split, 1.0), uvs, i, vlen+4);
+            },
+            TileShape::Step => {
+                // Riser occupies the bottom half of the tile, and the tread the top half
+                let half = th * 0.5;
+
+                // Positions (8)
+                p.push(tp);
+                tp[X] += tw;
+                p.push(tp);
+                tp[Y] += half;
+                p.push(tp);
+                tp[X] -= tw;
+                p.push(tp);
+                p.push(tp);
+                tp[X] += tw;
+                p.push(tp);
+                tp[Z] -= half;
+                p.push(tp);
+                tp[X] -= tw;
+                p.push(tp);
+
+                // Normals (8)
+                let s = [0.0, 0.0, 1.0];
+                let up = [0.0, 1.0, 0.0];
+                for _ in 0..4 { n.push(s); }
+                for _ in 0..4 { n.push(up); }
+
+                // UVs and indices
+                push_uv_indices(&uv_band(&tile_uvs, 0.0, 0.5), uvs, i, vlen);
+                push_uv_indices(&uv_band(&tile_uvs, 0.5, 1.0), uvs, i, vlen+4);
+            },
+            TileShape::SlopeStartE => {
+                // Vertices (6)
+                p.push(tp);
+                tp[X] += tw;
+                p.push(tp);
+                tp[X] -= tw;
+                tp[Y] += th;
+                p.push(tp);
+                tp[X] += tw;
+                tp[Y] -= th;
+                p.push(tp);
+                tp[Z] -= th;
+                p.push(tp);
+                tp[X] -= tw;
+                tp[Y] += th;
+                tp[Z] += th;
+                p.push(tp);
+
+                // Normals (6)
+                let s = [0.0, 0.0, 1.0];
+                let e = [1.0/SQRT_2, 1.0/SQRT_2, 0.0];
+                n.push(s);
+                n.push(s);
+                n.push(s);
+                n.push(e);
+                n.push(e);
+                n.push(e);
+
+                // UVs
+                uvs.push(uv1.to_array());
+                uvs.push(uv2.to_array());
+                uvs.push(uv4.to_array());
+                uvs.push(uv2.to_array());
+                uvs.push(uv3.to_array());
+                uvs.push(uv4.to_array());
+
+                // Indices
+                i.push(vlen);
+                i.push(vlen+1);
+                i.push(vlen+2);
+                i.push(vlen+3);
+                i.push(vlen+4);
+                i.push(vlen+5);
+            }
+            TileShape::SlopeStartW => {
+                // Vertices (6)
+                p.push(tp);
+                tp[X] += tw;
+                p.push(tp);
+                tp[Y] += th;
+                p.push(tp);
+                tp[X] -= tw;
+                tp[Y] -= th;
+                p.push(tp);
+                tp[X] += tw;
+                tp[Y] += th;
+                p.push(tp);
+                tp[X] -= tw;
+                tp[Y] -= th;
+                tp[Z] -= th;
+                p.push(tp);
+
+                // Normals (6)
+                let s = [0.0, 0.0, 1.0];
+                let w = [-1.0/SQRT_2, 1.0/SQRT_2, 0.0];
+                n.push(s);
+                n.push(s);
+                n.push(s);
+                n.push(w);
+                n.push(w);
+                n.push(w);
+
+                // UVs
+                uvs.push(uv1.to_array());
+                uvs.push(uv2.to_array());
+                uvs.push(uv3.to_array());
+                uvs.push(uv1.to_array());
+                uvs.push(uv3.to_array());
+                uvs.push(uv4.to_array());
+
+                // Indices
+                i.push(vlen);
+                i.push(vlen+1);
+                i.push(vlen+2);
+                i.push(vlen+3);
+                i.push(vlen+4);
+                i.push(vlen+5);
             }
+            TileShape::SlopeE | TileShape::SlopeEndE => {
+                // Positions (4)
+                p.push(tp);
+                tp[X] += tw;
+                tp[Y] -= th;
+                tp[Z] -= th;
+                p.push(tp);
+                tp[Z] -= th;
+                p.push(tp);
+                tp[X] -= tw;
+                tp[Y] += th;
+                tp[Z] += th;
+                p.push(tp);
+                // Normals (4)
+                let norm = [1.0/SQRT_2, 1.0/SQRT_2, 0.0];
+                for _ in 0..4 { n.push(norm); }
+                // UVs and indices
+                push_uv_indices(&tile_uvs, uvs, i, vlen);
+            },
+            TileShape::SlopeW | TileShape::SlopeEndW => {
+                // Positions (4)
+                p.push(tp);
+                tp[X] += tw;
+                tp[Y] += th;
+                tp[Z] += th;
+                p.push(tp);
+                tp[Z] -= th;
+                p.push(tp);
+                tp[X] -= tw;
+                tp[Y] -= th;
+                tp[Z] -= th;
+                p.push(tp);
+                // Normals (4)
+                let norm = [-1.0/SQRT_2, 1.0/SQRT_2, 0.0];
+                for _ in 0..4 { n.push(norm); }
+                // UVs and indices
+                push_uv_indices(&tile_uvs, uvs, i, vlen);
+            },
         }
     }
 }
 
+// Horizontal band of a tile's UVs, where bottom and top are fractions of the tile's height
+fn uv_band(tile_uvs: &[Vec2; 4], bottom: f32, top: f32) -> [Vec2; 4] {
+    let [uv1, uv2, uv3, uv4] = *tile_uvs;
+    [
+        uv1.lerp(uv4, bottom),
+        uv2.lerp(uv3, bottom),
+        uv2.lerp(uv3, top),
+        uv1.lerp(uv4, top)
+    ]
+}
+
 // Pushes 4 uv values and 6 indices (4 vertices)
 fn push_uv_indices(
-    mesh_data: &TileMeshData,
+    tile_uvs: &[Vec2; 4],
     uvs: &mut Vec<[f32; 2]>,
     indices: &mut Vec<u32>,
     vlen: u32
 ) {
-    uvs.push(mesh_data.uv1.to_array());
-    uvs.push(mesh_data.uv2.to_array());
-    uvs.push(mesh_data.uv3.to_array());
-    uvs.push(mesh_data.uv4.to_array());
+    for uv in tile_uvs {
+        uvs.push(uv.to_array());
+    }
 
     indices.push(vlen);
     indices.push(vlen+1);
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use bevy::asset::{AssetServer, HandleId};
+use bevy::utils::HashMap;
+
+/// Error encountered while loading, constructing or spawning a map.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapLoadError {
+    /// A file the map depends on could not be read
+    Io {
+        path: PathBuf,
+        message: String
+    },
+    /// The TMX file, or one of its tilesets, could not be parsed
+    Tmx(String),
+    /// The world file could not be parsed
+    World(String),
+    /// The asset server failed to load the map or one of its images
+    AssetFailed {
+        path: String
+    },
+    /// The images of an image collection tileset could not be packed into an atlas
+    Atlas {
+        tileset: String,
+        message: String
+    },
+    /// A tile was placed whose tileset has no image for it
+    MissingTileImage {
+        tileset: String,
+        tile_id: u32,
+        layer: String,
+        x: i32,
+        y: i32
+    },
+    /// A layer was not structured the way the map traverser expects
+    LayerStructure {
+        layer: String,
+        message: String
+    },
+    /// A meta tile had a "type" property that is not a known [`crate::map::TileType`]
+    UnknownTileType {
+        layer: String,
+        x: i32,
+        y: i32,
+        name: String
+    },
+    /// Tiles in a column were arranged in a way that could not be climbed
+    Climbing {
+        layer: String,
+        x: i32,
+        y: i32,
+        message: String
+    },
+    /// A custom property of the map had the wrong type or value
+    MapProperty {
+        name: String,
+        message: String
+    },
+    /// A baked map could not be written or read
+    Baked(String),
+    /// A map could not be exported as TMX
+    Export(String)
+}
+
+impl fmt::Display for MapLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io { path, message } => write!(f, "Failed to read '{}': {}", path.display(), message),
+            Self::Tmx(message) => write!(f, "Failed to parse map: {}", message),
+            Self::World(message) => write!(f, "Failed to parse world: {}", message),
+            Self::AssetFailed { path } => write!(f, "Failed to load asset '{}'", path),
+            Self::Atlas { tileset, message } => write!(f, "Failed to build atlas for tileset '{}': {}", tileset, message),
+            Self::MissingTileImage { tileset, tile_id, layer, x, y } => write!(f, "Tile {} of tileset '{}' has no image on group layer '{}' at {}, {}", tile_id, tileset, layer, x, y),
+            Self::LayerStructure { layer, message } => write!(f, "Invalid layer '{}': {}", layer, message),
+            Self::UnknownTileType { layer, x, y, name } => write!(f, "Unknown tile type '{}' on layer '{}' at {}, {}", name, layer, x, y),
+            Self::Climbing { layer, x, y, message } => write!(f, "{} on group layer '{}' at {}, {}", message, layer, x, y),
+            Self::MapProperty { name, message } => write!(f, "Invalid map property '{}': {}", name, message),
+            Self::Baked(message) => write!(f, "Invalid baked map: {}", message),
+            Self::Export(message) => write!(f, "Failed to export map: {}", message)
+        }
+    }
+}
+
+impl std::error::Error for MapLoadError {}
+
+impl From<tiled::Error> for MapLoadError {
+    fn from(error: tiled::Error) -> Self {
+        match error {
+            tiled::Error::ResourceLoadingError { path, err } => Self::Io {
+                path,
+                message: err.to_string()
+            },
+            other => Self::Tmx(other.to_string())
+        }
+    }
+}
+
+/// Fired when a map fails to load.
+/// By the time this is fired, the partially loaded map has been discarded and the [`crate::game::GameState`] stack has been restored.
+#[derive(Debug, Clone)]
+pub struct MapLoadFailedEvent {
+    /// Name of the map that failed to load
+    pub name: String,
+    /// Reason the map failed to load
+    pub error: MapLoadError
+}
+
+/// Resource holding the reasons map files failed to load, keyed by asset path.
+/// The asset server only remembers that a file failed, so map loaders leave the reason here for the loading systems to forward.
+#[derive(Debug, Clone, Default)]
+pub struct MapLoadErrors(Arc<Mutex<HashMap<PathBuf, MapLoadError>>>);
+
+impl MapLoadErrors {
+
+    /// Records the result of loading a file, replacing any earlier failure of that file
+    pub fn record<T>(&self, path: &Path, result: Result<T, MapLoadError>) -> Result<T, MapLoadError> {
+        let mut errors = self.0.lock().unwrap();
+        match &result {
+            Ok(_) => { errors.remove(path); }
+            Err(error) => { errors.insert(path.to_path_buf(), error.clone()); }
+        }
+        result
+    }
+
+    /// Takes the reason a file failed to load, if one was recorded
+    pub fn take(&self, path: &Path) -> Option<MapLoadError> {
+        self.0.lock().unwrap().remove(path)
+    }
+
+    /// Error for an asset that failed to load.
+    /// This is the reason its loader recorded, or [`MapLoadError::AssetFailed`] if it recorded none.
+    pub fn asset_failed(&self, asset_server: &AssetServer, handle_id: HandleId) -> MapLoadError {
+        let path = match asset_server.get_handle_path(handle_id) {
+            Some(asset_path) => asset_path.path().to_path_buf(),
+            None => return MapLoadError::AssetFailed { path: String::new() }
+        };
+        self.take(&path).unwrap_or_else(|| MapLoadError::AssetFailed { path: path.display().to_string() })
+    }
+}
+
+
+#[test]
+fn test_map_load_errors() {
+    let errors = MapLoadErrors::default();
+    let path = Path::new("maps/broken.tmx");
+    let error = MapLoadError::Tmx("unexpected end of file".to_owned());
+    assert_eq!(Err(error.clone()), errors.record::<()>(path, Err(error.clone())));
+    assert_eq!(Some(error.clone()), errors.take(path));
+    assert_eq!(None, errors.take(path));
+
+    // Loading the file again without errors forgets the earlier failure
+    let _ = errors.record::<()>(path, Err(error));
+    assert_eq!(Ok(()), errors.record(path, Ok(())));
+    assert_eq!(None, errors.take(path));
+}
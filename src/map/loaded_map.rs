@@ -0,0 +1,155 @@
+use crate::map::{VidyaMap, VidyaWorld, CurrentMap, TileBounds, TileType};
+use crate::physics::{Terrain, Coords};
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use tiled::Properties;
+
+/// Resource describing the map that is currently spawned.
+/// Unlike [`crate::map::CurrentMap`], this outlives the loading process, and is removed when the screen changes.
+///
+/// Tile coordinates are those of the map file, where y grows downwards.
+/// Since rows further up the map file are either further north or higher up, converting from world space to tiles
+/// assumes that the position is on the surface of the terrain.
+#[derive(Debug, Clone)]
+pub struct LoadedMap {
+    pub name: String,
+    pub map_handle: Handle<VidyaMap>,
+    /// World the map was loaded from, if it was a world
+    pub world_handle: Option<Handle<VidyaWorld>>,
+    /// Tiles the map occupies
+    pub bounds: TileBounds,
+    /// Size of a tile in world space. Tiles are as deep as they are tall.
+    pub tile_size: Vec3,
+    /// Custom properties of the map
+    pub properties: Properties,
+    /// Collision type of every tile with a meta tile, keyed by tile x/y
+    pub meta_tiles: HashMap<IVec2, TileType>,
+    /// Tiles whose type was changed with [`crate::map::EditTerrainEvent::SetTileType`] since the map spawned
+    pub edited_tiles: HashMap<IVec2, TileType>
+}
+
+impl LoadedMap {
+
+    /// Remembers the map that is being spawned
+    pub fn new(current_map: &CurrentMap) -> Self {
+        Self {
+            name: current_map.name.clone(),
+            map_handle: current_map.map_handle.clone(),
+            world_handle: current_map.world_handle.clone(),
+            bounds: current_map.bounds,
+            tile_size: current_map.tile_size,
+            properties: current_map.properties.clone(),
+            meta_tiles: current_map.meta_tiles.clone(),
+            edited_tiles: current_map.meta_overrides.clone()
+        }
+    }
+
+    /// Tile that a position on the surface of the terrain was drawn on
+    pub fn world_to_tile(&self, position: Vec3) -> IVec2 {
+        let row = ((position.y - position.z) / self.tile_size.y).floor() as i32;
+        IVec2::new(
+            (position.x / self.tile_size.x).floor() as i32,
+            self.bottom_row() - row
+        )
+    }
+
+    /// Position of the near-left corner of a tile, assuming that it's on flat ground
+    pub fn tile_to_world(&self, tile: IVec2) -> Vec3 {
+        Vec3::new(
+            tile.x as f32 * self.tile_size.x,
+            0.0,
+            -((self.bottom_row() - tile.y) as f32) * self.tile_size.z
+        )
+    }
+
+    /// Terrain coordinates that a position falls in
+    pub fn world_to_coords(&self, position: Vec3) -> Coords {
+        let c = (position / self.tile_size).floor();
+        Coords::new(c.x as i32, c.y as i32, c.z as i32)
+    }
+
+    /// Position of the bottom-left-far corner of terrain coordinates
+    pub fn coords_to_world(&self, coords: Coords) -> Vec3 {
+        Vec3::new(coords.x as f32, coords.y as f32, coords.z as f32) * self.tile_size
+    }
+
+    /// Tile that terrain coordinates were drawn on, if they are on the surface of the terrain
+    pub fn coords_to_tile(&self, coords: Coords) -> IVec2 {
+        self.world_to_tile(self.coords_to_world(coords))
+    }
+
+    /// Terrain coordinates of a tile, assuming that it's on flat ground
+    pub fn tile_to_coords(&self, tile: IVec2) -> Coords {
+        self.world_to_coords(self.tile_to_world(tile))
+    }
+
+    /// Height of the highest surface of the terrain at x, z.
+    /// None if there is no terrain there.
+    /// The spawned terrain only holds chunks near physics entities, so pass [`crate::map::StreamedMap::terrain`] to query the whole map.
+    pub fn ground_height(&self, terrain: &Terrain, x: f32, z: f32) -> Option<f32> {
+        let piece_size = terrain.piece_size();
+        let chunk_height = terrain.chunk_size().y as i32;
+        let (min_y, max_y) = terrain
+            .chunks()
+            .map(|(coords, _)| coords.y)
+            .fold(None, |range, y| match range {
+                Some((min, max)) => Some((y.min(min), y.max(max))),
+                None => Some((y, y))
+            })?;
+        let (px, pz) = (x / piece_size.x, z / piece_size.z);
+        let (cx, cz) = (px.floor(), pz.floor());
+        let point = Vec2::new(px - cx, pz - cz);
+        for cy in (min_y * chunk_height .. (max_y + 1) * chunk_height).rev() {
+            let piece = terrain.get(Coords::new(cx as i32, cy, cz as i32));
+            if let Some(height) = piece.and_then(|piece| piece.surface_height(point)) {
+                return Some((cy as f32 + height) * piece_size.y);
+            }
+        }
+        None
+    }
+
+    /// Collision type of the meta tile at a position on the surface of the terrain.
+    /// Tiles without a meta tile are floors. None if outside of the map.
+    pub fn tile_type_at(&self, position: Vec3) -> Option<TileType> {
+        let tile = self.world_to_tile(position);
+        if !self.bounds.contains(tile.x, tile.y) {
+            return None;
+        }
+        Some(self.meta_tiles.get(&tile).copied().unwrap_or(TileType::Floor))
+    }
+
+    // Row of the map that sits at z = 0
+    fn bottom_row(&self) -> i32 {
+        self.bounds.max.y - 1
+    }
+}
+
+
+#[test]
+fn test_loaded_map_queries() {
+    use crate::physics::TerrainPiece;
+
+    let mut current_map = CurrentMap::new("test.tmx", Handle::default());
+    current_map.bounds = TileBounds::new(IVec2::ZERO, IVec2::new(4, 4));
+    current_map.meta_tiles.insert(IVec2::new(1, 2), TileType::Wall);
+    let loaded_map = LoadedMap::new(&current_map);
+
+    // Bottom row sits at z = 0, and rows above it go north or up
+    assert_eq!(Vec3::new(16.0, 0.0, -16.0), loaded_map.tile_to_world(IVec2::new(1, 2)));
+    assert_eq!(IVec2::new(1, 2), loaded_map.world_to_tile(Vec3::new(20.0, 0.0, -20.0)));
+    assert_eq!(IVec2::new(1, 2), loaded_map.world_to_tile(Vec3::new(20.0, 20.0, 0.0)));
+    assert_eq!(Coords::new(1, 0, -1), loaded_map.tile_to_coords(IVec2::new(1, 2)));
+    assert_eq!(Some(TileType::Wall), loaded_map.tile_type_at(Vec3::new(20.0, 0.0, -20.0)));
+    assert_eq!(Some(TileType::Floor), loaded_map.tile_type_at(Vec3::new(4.0, 0.0, -4.0)));
+    assert_eq!(None, loaded_map.tile_type_at(Vec3::new(-4.0, 0.0, -4.0)));
+
+    // Highest surface wins
+    let mut terrain = Terrain::new(Vec3::new(16.0, 16.0, 16.0), UVec3::new(4, 4, 4));
+    *terrain.get_or_create_mut(Coords::new(0, -1, -1)) = TerrainPiece::Cuboid;
+    *terrain.get_or_create_mut(Coords::new(0, 0, -1)) = TerrainPiece::Slope;
+    *terrain.get_or_create_mut(Coords::new(1, -1, -1)) = TerrainPiece::Cuboid;
+    assert_eq!(Some(12.0), loaded_map.ground_height(&terrain, 8.0, -12.0));
+    assert_eq!(Some(0.0), loaded_map.ground_height(&terrain, 24.0, -8.0));
+    assert_eq!(None, loaded_map.ground_height(&terrain, 40.0, -8.0));
+}
@@ -0,0 +1,873 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use tiled::{FilesystemResourceCache, LayerType, Loader, Map, ObjectLayer, ObjectShape, PropertyValue, Properties, TileLayer, Tileset};
+
+use crate::extensions::PathExt;
+use crate::map::{
+    CurrentMap, CurrentMapGraphics, LoadedMap, MapConfig, MapLoadError, TileBounds, TileType, VidyaMap,
+    map_tile_bounds, placeholder_atlas, process_tiled_map
+};
+
+
+// Width and height of the chunks infinite layers are written in, measured in tiles
+const TILED_CHUNK_SIZE: i32 = 16;
+
+// Bits of a gid that flip its tile
+const FLIPPED_HORIZONTALLY: u32 = 0x80000000;
+const FLIPPED_VERTICALLY: u32 = 0x40000000;
+const FLIPPED_DIAGONALLY: u32 = 0x20000000;
+
+/// Builds a map in code, placing meta tile types and terrain tiles the way a map file would.
+/// The map is written as TMX and read back, so it goes through the same climbing pipeline as maps made in Tiled.
+///
+/// Tiles are placed in groups, which are the group layers of the map. Tile y grows downwards, like in Tiled.
+#[derive(Debug, Clone)]
+pub struct MapBuilder {
+    name: String,
+    bounds: TileBounds,
+    tile_size: UVec2,
+    properties: Properties,
+    tilesets: Vec<BuilderTileset>,
+    groups: Vec<BuilderGroup>,
+    object_layers: Vec<BuilderObjectLayer>
+}
+
+/// Meta layer that a meta tile is placed on, which decides whether its type shapes geometry, collision or both.
+/// The other one is treated as a floor.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum MetaLayerType {
+    GeomColl,
+    Geom,
+    Coll
+}
+
+impl MetaLayerType {
+
+    /// Value of the "type" property of layers of this type
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::GeomColl => "geom_coll",
+            Self::Geom => "geom",
+            Self::Coll => "coll"
+        }
+    }
+}
+
+/// Object placed on an object layer of a [`MapBuilder`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuilderObject {
+    pub name: String,
+    pub typ: String,
+    /// Position in pixels, measured from the top-left corner of the map
+    pub position: Vec2,
+    /// Size of the object in pixels. Objects without a size are points.
+    pub size: Vec2,
+    pub properties: Properties
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BuilderTileset {
+    name: String,
+    tile_size: UVec2,
+    margin: u32,
+    spacing: u32,
+    columns: u32,
+    tile_count: u32,
+    /// Image of the whole tileset. None for image collection tilesets.
+    image: Option<BuilderImage>,
+    properties: Properties,
+    /// Tiles that have images, animations or properties of their own
+    tiles: Vec<BuilderTile>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BuilderImage {
+    /// Path relative to the asset folder
+    source: String,
+    size: UVec2
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BuilderTile {
+    id: u32,
+    image: Option<BuilderImage>,
+    /// Tile id and duration in milliseconds of every frame
+    animation: Vec<(u32, u32)>,
+    properties: Properties
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BuilderGroup {
+    name: String,
+    /// Includes the "elevation" and "bridge" properties
+    properties: Properties,
+    meta_tiles: HashMap<IVec2, (MetaLayerType, TileType)>,
+    /// Terrain layers, from the bottom layer up
+    terrain_layers: Vec<BuilderTileLayer>,
+    object_layers: Vec<BuilderObjectLayer>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BuilderTileLayer {
+    name: String,
+    tiles: HashMap<IVec2, BuilderLayerTile>
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct BuilderLayerTile {
+    tileset: usize,
+    id: u32,
+    flip_h: bool,
+    flip_v: bool,
+    flip_d: bool
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BuilderObjectLayer {
+    name: String,
+    objects: Vec<BuilderObject>
+}
+
+impl MapBuilder {
+
+    /// Empty map, measured in tiles.
+    /// The name is the path of the map file relative to the asset folder, which tileset images are written relative to.
+    pub fn new(name: impl Into<String>, size: UVec2, tile_size: UVec2) -> Self {
+        Self {
+            name: name.into(),
+            bounds: TileBounds::new(IVec2::ZERO, size.as_ivec2()),
+            tile_size,
+            properties: Properties::new(),
+            tilesets: Vec::new(),
+            groups: Vec::new(),
+            object_layers: Vec::new()
+        }
+    }
+
+    /// Copies the layers, tilesets and properties of a tiled map, so that it can be edited and written back out.
+    /// Image paths of the tiled map are made relative to the asset folder, and tilesets that no terrain tile uses are left out.
+    /// Polygons and polylines become points, and tile objects lose their tile.
+    pub fn from_tiled_map(name: impl Into<String>, tiled_map: &Map, asset_folder: impl AsRef<Path>) -> Result<Self, MapLoadError> {
+        let asset_folder = asset_folder.as_ref();
+        let mut builder = Self {
+            bounds: map_tile_bounds(tiled_map),
+            properties: tiled_map.properties.clone(),
+            ..Self::new(name, UVec2::ZERO, UVec2::new(tiled_map.tile_width, tiled_map.tile_height))
+        };
+        for layer in tiled_map.layers() {
+            match layer.layer_type() {
+                LayerType::GroupLayer(group_layer) => {
+                    let mut group = BuilderGroup {
+                        name: layer.name.clone(),
+                        properties: layer.properties.clone(),
+                        meta_tiles: HashMap::default(),
+                        terrain_layers: Vec::new(),
+                        object_layers: Vec::new()
+                    };
+                    for sub_layer in group_layer.layers() {
+                        let layer_name = format!("{}/{}", &layer.name, &sub_layer.name);
+                        match sub_layer.layer_type() {
+                            LayerType::TileLayer(tile_layer) => {
+                                let layer_type = match sub_layer.properties.get("type") {
+                                    Some(PropertyValue::StringValue(layer_type)) => layer_type.as_str(),
+                                    _ => "terrain"
+                                };
+                                let meta_layer_type = match layer_type {
+                                    "terrain" => None,
+                                    "geom_coll" => Some(MetaLayerType::GeomColl),
+                                    "geom" => Some(MetaLayerType::Geom),
+                                    "coll" => Some(MetaLayerType::Coll),
+                                    _ => return Err(MapLoadError::LayerStructure {
+                                        layer: layer_name,
+                                        message: format!("Unexpected tile layer type '{}'", layer_type)
+                                    })
+                                };
+                                match meta_layer_type {
+                                    Some(meta_layer_type) => builder.read_meta_layer(&mut group, &tile_layer, meta_layer_type, &layer_name)?,
+                                    None => {
+                                        let tiles = builder.read_terrain_layer(&tile_layer);
+                                        group.terrain_layers.push(BuilderTileLayer { name: sub_layer.name.clone(), tiles });
+                                    }
+                                }
+                            }
+                            LayerType::ObjectLayer(object_layer) => group.object_layers.push(read_object_layer(&sub_layer.name, &object_layer)),
+                            _ => return Err(MapLoadError::LayerStructure {
+                                layer: layer_name,
+                                message: "Sub layer must be a tile layer or an object layer".to_owned()
+                            })
+                        }
+                    }
+                    builder.groups.push(group);
+                }
+                LayerType::ObjectLayer(object_layer) => builder.object_layers.push(read_object_layer(&layer.name, &object_layer)),
+                _ => return Err(MapLoadError::LayerStructure {
+                    layer: layer.name.clone(),
+                    message: "Root layers must be group layers or object layers".to_owned()
+                })
+            }
+        }
+
+        // Keeps the tilesets that terrain tiles use.
+        // The rest, such as the one meta tiles came from, are dropped, as meta tiles get a tileset of their own when written.
+        let mut tileset_indices = HashMap::default();
+        for (index, tileset) in tiled_map.tilesets().iter().enumerate() {
+            let is_used = builder.terrain_tiles().any(|tile| tile.tileset == index);
+            if is_used {
+                tileset_indices.insert(index, builder.tilesets.len());
+                builder.tilesets.push(BuilderTileset::from_tileset(tileset, asset_folder));
+            }
+        }
+        for group in &mut builder.groups {
+            for tile in group.terrain_layers.iter_mut().flat_map(|layer| layer.tiles.values_mut()) {
+                tile.tileset = tileset_indices[&tile.tileset];
+            }
+        }
+        Ok(builder)
+    }
+
+    /// Copies the spawned map, including the tiles whose type was changed with [`crate::map::EditTerrainEvent::SetTileType`].
+    /// Pieces set directly with [`crate::map::EditTerrainEvent::SetPieces`] have no tiles to go on, and are lost.
+    /// Fails if the map was loaded from a world or a baked map, or if its map file is not loaded.
+    pub fn from_loaded_map(loaded_map: &LoadedMap, vidya_maps: &Assets<VidyaMap>, asset_folder: impl AsRef<Path>) -> Result<Self, MapLoadError> {
+        if loaded_map.world_handle.is_some() {
+            return Err(MapLoadError::Export(format!("'{}' is a world, whose maps must be exported one at a time", loaded_map.name)));
+        }
+        let vidya_map = vidya_maps
+            .get(&loaded_map.map_handle)
+            .ok_or_else(|| MapLoadError::Export(format!("'{}' has no map file loaded", loaded_map.name)))?;
+        let mut builder = Self::from_tiled_map(loaded_map.name.clone(), &vidya_map.tiled_map, asset_folder)?;
+        builder.set_edited_tiles(&loaded_map.edited_tiles);
+        Ok(builder)
+    }
+
+    /// Name of the map, used as its file name
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Size of the map in tiles
+    pub fn size(&self) -> UVec2 { self.bounds.size().as_uvec2() }
+
+    /// Sets a custom property of the map, such as those read by [`crate::map::MapSettings`]
+    pub fn set_property(&mut self, name: impl Into<String>, value: PropertyValue) -> &mut Self {
+        self.properties.insert(name.into(), value);
+        self
+    }
+
+    /// Adds a tileset made of a single image, whose path is relative to the asset folder.
+    /// Returns the index of the tileset.
+    pub fn add_tileset(&mut self, name: impl Into<String>, image: impl Into<String>, image_size: UVec2) -> usize {
+        let columns = image_size.x / self.tile_size.x;
+        self.tilesets.push(BuilderTileset {
+            name: name.into(),
+            tile_size: self.tile_size,
+            margin: 0,
+            spacing: 0,
+            columns,
+            tile_count: columns * (image_size.y / self.tile_size.y),
+            image: Some(BuilderImage { source: image.into(), size: image_size }),
+            properties: Properties::new(),
+            tiles: Vec::new()
+        });
+        self.tilesets.len() - 1
+    }
+
+    /// Adds a group layer whose climbers start at an elevation, in tiles.
+    /// Bridges only write a single tile of collision beneath their floors, like the "bridge" property of group layers.
+    /// Returns the index of the group.
+    pub fn add_group(&mut self, name: impl Into<String>, elevation: i32, bridge: bool) -> usize {
+        let mut properties = Properties::new();
+        properties.insert("elevation".to_owned(), PropertyValue::IntValue(elevation));
+        properties.insert("bridge".to_owned(), PropertyValue::BoolValue(bridge));
+        self.groups.push(BuilderGroup {
+            name: name.into(),
+            properties,
+            meta_tiles: HashMap::default(),
+            terrain_layers: Vec::new(),
+            object_layers: Vec::new()
+        });
+        self.groups.len() - 1
+    }
+
+    /// Places a meta tile of the specified type, used for both geometry and collision.
+    /// Tiles without a meta tile are floors.
+    pub fn set_tile_type(&mut self, group: usize, tile: IVec2, tile_type: TileType) -> &mut Self {
+        self.set_meta_tile(group, tile, MetaLayerType::GeomColl, tile_type)
+    }
+
+    /// Places a meta tile on a specific meta layer, replacing any meta tile already there
+    pub fn set_meta_tile(&mut self, group: usize, tile: IVec2, meta_layer_type: MetaLayerType, tile_type: TileType) -> &mut Self {
+        self.groups[group].meta_tiles.insert(tile, (meta_layer_type, tile_type));
+        self
+    }
+
+    /// Places a terrain tile from a tileset on one of the group's terrain layers.
+    /// Layers are created as needed, and higher layers are drawn on top of lower ones.
+    pub fn set_tile(&mut self, group: usize, layer: usize, tile: IVec2, tileset: usize, tile_id: u32) -> &mut Self {
+        let layers = &mut self.groups[group].terrain_layers;
+        while layers.len() <= layer {
+            let name = format!("terrain {}", layers.len() + 1);
+            layers.push(BuilderTileLayer { name, tiles: HashMap::default() });
+        }
+        layers[layer].tiles.insert(tile, BuilderLayerTile {
+            tileset,
+            id: tile_id,
+            flip_h: false,
+            flip_v: false,
+            flip_d: false
+        });
+        self
+    }
+
+    /// Places an object on one of the group's object layers, which is created if it doesn't exist
+    pub fn add_object(&mut self, group: usize, layer: &str, object: BuilderObject) -> &mut Self {
+        let layers = &mut self.groups[group].object_layers;
+        let index = match layers.iter().position(|object_layer| object_layer.name == layer) {
+            Some(index) => index,
+            None => {
+                layers.push(BuilderObjectLayer { name: layer.to_owned(), objects: Vec::new() });
+                layers.len() - 1
+            }
+        };
+        layers[index].objects.push(object);
+        self
+    }
+
+    /// Changes the type of tiles the way [`crate::map::EditTerrainEvent::SetTileType`] does.
+    /// Every group that occupies a tile gets a meta tile of the new type there.
+    pub fn set_edited_tiles(&mut self, edited_tiles: &HashMap<IVec2, TileType>) -> &mut Self {
+        for group in &mut self.groups {
+            let elevation = match group.properties.get("elevation") {
+                Some(PropertyValue::IntValue(elevation)) => *elevation,
+                _ => 0
+            };
+            let bridge = matches!(group.properties.get("bridge"), Some(PropertyValue::BoolValue(true)));
+            for (tile, tile_type) in edited_tiles {
+                let is_occupied =
+                    (elevation == 0 && !bridge) ||
+                    group.meta_tiles.contains_key(tile) ||
+                    group.terrain_layers.iter().any(|layer| layer.tiles.contains_key(tile));
+                if is_occupied {
+                    group.meta_tiles.insert(*tile, (MetaLayerType::GeomColl, *tile_type));
+                }
+            }
+        }
+        self
+    }
+
+    /// Writes the map as TMX, with its tilesets embedded.
+    /// Meta tiles reference a tileset with one tile per [`TileType`], which comes before every other tileset.
+    /// Maps whose tiles start at 0, 0 are written as finite maps, and others as infinite maps.
+    pub fn to_tmx(&self) -> String {
+        let size = self.size();
+        let (tw, th) = (self.tile_size.x, self.tile_size.y);
+        let infinite = self.bounds.min != IVec2::ZERO;
+        let map_folder = Path::new(&self.name).parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut body = String::new();
+        let mut layer_id = 1;
+        let mut next_object_id = 1;
+        write_properties(&mut body, &self.properties, 1);
+
+        // Meta tileset comes first, followed by the tilesets added
+        writeln!(body, r#" <tileset firstgid="1" name="meta" tilewidth="{}" tileheight="{}" tilecount="{}" columns="0">"#, tw, th, meta_tile_count()).unwrap();
+        for tile_type in TileType::all() {
+            writeln!(body, r#"  <tile id="{}">"#, u8::from(tile_type)).unwrap();
+            let mut properties = Properties::new();
+            properties.insert("type".to_owned(), PropertyValue::StringValue(tile_type.name().to_owned()));
+            write_properties(&mut body, &properties, 3);
+            writeln!(body, r#"  </tile>"#).unwrap();
+        }
+        writeln!(body, r#" </tileset>"#).unwrap();
+        let first_gids = self.first_gids();
+        for (tileset, first_gid) in self.tilesets.iter().zip(&first_gids) {
+            tileset.write(&mut body, *first_gid, &map_folder);
+        }
+
+        // Every group holds its meta layers, followed by its terrain layers and its object layers
+        let meta_gid = |tile_type: TileType| u8::from(tile_type) as u32 + 1;
+        for group in &self.groups {
+            writeln!(body, r#" <group id="{}" name="{}">"#, layer_id, escape(&group.name)).unwrap();
+            layer_id += 1;
+            write_properties(&mut body, &group.properties, 2);
+            for meta_layer_type in [MetaLayerType::GeomColl, MetaLayerType::Geom, MetaLayerType::Coll] {
+                let gids: HashMap<IVec2, u32> = group.meta_tiles
+                    .iter()
+                    .filter(|(_, (layer_type, _))| *layer_type == meta_layer_type)
+                    .map(|(tile, (_, tile_type))| (*tile, meta_gid(*tile_type)))
+                    .collect();
+                if !gids.is_empty() {
+                    self.write_tile_layer(&mut body, layer_id, meta_layer_type.name(), meta_layer_type.name(), &gids);
+                    layer_id += 1;
+                }
+            }
+            for layer in &group.terrain_layers {
+                let gids: HashMap<IVec2, u32> = layer.tiles
+                    .iter()
+                    .map(|(tile, layer_tile)| (*tile, layer_tile.gid(first_gids[layer_tile.tileset])))
+                    .collect();
+                self.write_tile_layer(&mut body, layer_id, &layer.name, "terrain", &gids);
+                layer_id += 1;
+            }
+            for object_layer in &group.object_layers {
+                object_layer.write(&mut body, layer_id, &mut next_object_id, 2);
+                layer_id += 1;
+            }
+            writeln!(body, r#" </group>"#).unwrap();
+        }
+        for object_layer in &self.object_layers {
+            object_layer.write(&mut body, layer_id, &mut next_object_id, 1);
+            layer_id += 1;
+        }
+        writeln!(body, "</map>").unwrap();
+
+        // Header comes last, as it holds the ids that the next layer and object added in Tiled will get
+        let mut header = String::new();
+        writeln!(header, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(
+            header,
+            r#"<map version="1.8" orientation="orthogonal" renderorder="right-down" width="{}" height="{}" tilewidth="{}" tileheight="{}" infinite="{}" nextlayerid="{}" nextobjectid="{}">"#,
+            size.x, size.y, tw, th, infinite as u8, layer_id, next_object_id
+        ).unwrap();
+        header + &tmx
+    }
+
+    /// Writes the map as a TMX file in the asset folder, at the path given by its name
+    pub fn write_tmx(&self, asset_folder: impl AsRef<Path>) -> Result<(), MapLoadError> {
+        let path = asset_folder.as_ref().join(&self.name);
+        let io_error = |error: std::io::Error| MapLoadError::Io { path: path.clone(), message: error.to_string() };
+        if let Some(folder) = path.parent() {
+            std::fs::create_dir_all(folder).map_err(io_error)?;
+        }
+        std::fs::write(&path, self.to_tmx()).map_err(io_error)
+    }
+
+    /// Reads the map back as a tiled map, as if it had been loaded from the asset folder
+    pub fn build_tiled_map(&self) -> Result<Map, MapLoadError> {
+        let mut loader = Loader::with_cache(FilesystemResourceCache::new());
+        let tmx = self.to_tmx();
+        let map = loader.load_tmx_map_from(tmx.as_bytes(), Path::new(&self.name))?;
+        Ok(map)
+    }
+
+    /// Runs the map through the climbing pipeline, producing its terrain, metadata and chunk graphics.
+    /// Tileset images are left unloaded. Use [`MapBuilder::load_images`] before spawning the graphics.
+    /// Image collection tilesets are never packed into atlases, so their tiles all sample the corner of a placeholder atlas.
+    pub fn build(&self, map_config: &MapConfig) -> Result<(CurrentMap, CurrentMapGraphics), MapLoadError> {
+        let tiled_map = self.build_tiled_map()?;
+        let mut current_map = CurrentMap::new(self.name.clone(), Handle::default());
+        let mut current_map_graphics = CurrentMapGraphics::new(map_config.chunk_size);
+        for tileset in tiled_map.tilesets() {
+            let atlas = match tileset.image {
+                Some(_) => None,
+                None => Some(placeholder_atlas(tileset))
+            };
+            current_map_graphics.tileset_handles.push(None);
+            current_map_graphics.tileset_atlases.push(atlas);
+            current_map_graphics.collection_handles.push(Vec::new());
+        }
+        process_tiled_map(&tiled_map, map_config.flip_y, &mut current_map, &mut current_map_graphics)?;
+        Ok((current_map, current_map_graphics))
+    }
+
+    /// Begins loading the tileset images of graphics built by [`MapBuilder::build`]
+    pub fn load_images(&self, current_map_graphics: &mut CurrentMapGraphics, asset_server: &AssetServer) {
+        for (index, tileset) in self.tilesets.iter().enumerate() {
+            if let Some(image) = &tileset.image {
+                current_map_graphics.tileset_handles[index + 1] = Some(asset_server.load(image.source.as_str()));
+            }
+        }
+    }
+
+    // Every terrain tile of every group
+    fn terrain_tiles(&self) -> impl Iterator<Item = &BuilderLayerTile> {
+        self.groups
+            .iter()
+            .flat_map(|group| &group.terrain_layers)
+            .flat_map(|layer| layer.tiles.values())
+    }
+
+    // First gid of every tileset added, which come after the meta tileset
+    fn first_gids(&self) -> Vec<u32> {
+        let mut first_gid = meta_tile_count() + 1;
+        self.tilesets
+            .iter()
+            .map(|tileset| {
+                let gid = first_gid;
+                first_gid += tileset.tile_count.max(tileset.tiles.iter().map(|tile| tile.id + 1).max().unwrap_or(0));
+                gid
+            })
+            .collect()
+    }
+
+    // Reads the meta tiles of a meta layer into a group
+    fn read_meta_layer(
+        &self,
+        group: &mut BuilderGroup,
+        tile_layer: &TileLayer,
+        meta_layer_type: MetaLayerType,
+        layer_name: &str
+    ) -> Result<(), MapLoadError> {
+        for (x, y) in self.tile_positions() {
+            let layer_tile = match tile_layer.get_tile(x, y) {
+                Some(layer_tile) => layer_tile,
+                None => continue
+            };
+            let name = match layer_tile.get_tile().and_then(|tile| tile.properties.get("type").cloned()) {
+                Some(PropertyValue::StringValue(name)) => name,
+                _ => "floor".to_owned()
+            };
+            let tile_type = TileType::from_str(&name).ok_or_else(|| MapLoadError::UnknownTileType {
+                layer: layer_name.to_owned(),
+                x,
+                y,
+                name: name.clone()
+            })?;
+            group.meta_tiles.entry(IVec2::new(x, y)).or_insert((meta_layer_type, tile_type));
+        }
+        Ok(())
+    }
+
+    // Reads the tiles of a terrain layer
+    fn read_terrain_layer(&self, tile_layer: &TileLayer) -> HashMap<IVec2, BuilderLayerTile> {
+        self.tile_positions()
+            .filter_map(|(x, y)| {
+                let tile = tile_layer.get_tile(x, y)?;
+                Some((IVec2::new(x, y), BuilderLayerTile {
+                    tileset: tile.tileset_index(),
+                    id: tile.id(),
+                    flip_h: tile.flip_h,
+                    flip_v: tile.flip_v,
+                    flip_d: tile.flip_d
+                }))
+            })
+            .collect()
+    }
+
+    // Every tile position within the bounds of the map
+    fn tile_positions(&self) -> impl Iterator<Item = (i32, i32)> {
+        let bounds = self.bounds;
+        (bounds.min.y..bounds.max.y).flat_map(move |y| (bounds.min.x..bounds.max.x).map(move |x| (x, y)))
+    }
+
+    // Writes a tile layer as CSV, where tiles not listed are empty.
+    // Infinite maps get every chunk within their bounds, so that reading them back yields the same bounds.
+    fn write_tile_layer(&self, tmx: &mut String, id: u32, name: &str, layer_type: &str, gids: &HashMap<IVec2, u32>) {
+        let size = self.size();
+        let csv = |min: IVec2, size: IVec2| -> String {
+            (min.y..min.y + size.y)
+                .map(|y| (min.x..min.x + size.x)
+                    .map(|x| gids.get(&IVec2::new(x, y)).copied().unwrap_or(0).to_string())
+                    .collect::<Vec<_>>()
+                    .join(","))
+                .collect::<Vec<_>>()
+                .join(",\n")
+        };
+        writeln!(tmx, r#"  <layer id="{}" name="{}" width="{}" height="{}">"#, id, escape(name), size.x, size.y).unwrap();
+        let mut properties = Properties::new();
+        properties.insert("type".to_owned(), PropertyValue::StringValue(layer_type.to_owned()));
+        write_properties(tmx, &properties, 3);
+        writeln!(tmx, r#"   <data encoding="csv">"#).unwrap();
+        if self.bounds.min == IVec2::ZERO {
+            writeln!(tmx, "{}", csv(IVec2::ZERO, self.bounds.max)).unwrap();
+        }
+        else {
+            let chunk_min = IVec2::new(self.bounds.min.x.div_euclid(TILED_CHUNK_SIZE), self.bounds.min.y.div_euclid(TILED_CHUNK_SIZE));
+            let chunk_max = IVec2::new((self.bounds.max.x - 1).div_euclid(TILED_CHUNK_SIZE), (self.bounds.max.y - 1).div_euclid(TILED_CHUNK_SIZE));
+            for chunk_y in chunk_min.y..=chunk_max.y {
+                for chunk_x in chunk_min.x..=chunk_max.x {
+                    let min = IVec2::new(chunk_x, chunk_y) * TILED_CHUNK_SIZE;
+                    writeln!(tmx, r#"    <chunk x="{}" y="{}" width="{}" height="{}">"#, min.x, min.y, TILED_CHUNK_SIZE, TILED_CHUNK_SIZE).unwrap();
+                    writeln!(tmx, "{}", csv(min, IVec2::splat(TILED_CHUNK_SIZE))).unwrap();
+                    writeln!(tmx, r#"    </chunk>"#).unwrap();
+                }
+            }
+        }
+        writeln!(tmx, r#"   </data>"#).unwrap();
+        writeln!(tmx, r#"  </layer>"#).unwrap();
+    }
+}
+
+impl BuilderTileset {
+    fn from_tileset(tileset: &Tileset, asset_folder: &Path) -> Self {
+        let image = |image: &tiled::Image| BuilderImage {
+            source: image.source.normalize().relativize(asset_folder).to_string_lossy().into_owned(),
+            size: UVec2::new(image.width as u32, image.height as u32)
+        };
+        let tiles = tileset
+            .tiles()
+            .filter(|(_, tile)| tile.image.is_some() || tile.animation.is_some() || !tile.properties.is_empty())
+            .map(|(id, tile)| BuilderTile {
+                id,
+                image: tile.image.as_ref().map(image),
+                animation: tile.animation
+                    .iter()
+                    .flatten()
+                    .map(|frame| (frame.tile_id, frame.duration))
+                    .collect(),
+                properties: tile.properties.clone()
+            })
+            .collect();
+        Self {
+            name: tileset.name.clone(),
+            tile_size: UVec2::new(tileset.tile_width, tileset.tile_height),
+            margin: tileset.margin,
+            spacing: tileset.spacing,
+            columns: tileset.columns,
+            tile_count: tileset.tilecount,
+            image: tileset.image.as_ref().map(image),
+            properties: tileset.properties.clone(),
+            tiles
+        }
+    }
+
+    // Writes the tileset, embedded in a map whose folder is relative to the asset folder
+    fn write(&self, tmx: &mut String, first_gid: u32, map_folder: &Path) {
+        writeln!(
+            tmx,
+            r#" <tileset firstgid="{}" name="{}" tilewidth="{}" tileheight="{}" spacing="{}" margin="{}" tilecount="{}" columns="{}">"#,
+            first_gid, escape(&self.name), self.tile_size.x, self.tile_size.y, self.spacing, self.margin, self.tile_count, self.columns
+        ).unwrap();
+        write_properties(tmx, &self.properties, 2);
+        if let Some(image) = &self.image {
+            image.write(tmx, map_folder, 2);
+        }
+        for tile in &self.tiles {
+            writeln!(tmx, r#"  <tile id="{}">"#, tile.id).unwrap();
+            write_properties(tmx, &tile.properties, 3);
+            if let Some(image) = &tile.image {
+                image.write(tmx, map_folder, 3);
+            }
+            if !tile.animation.is_empty() {
+                writeln!(tmx, r#"   <animation>"#).unwrap();
+                for (tile_id, duration) in &tile.animation {
+                    writeln!(tmx, r#"    <frame tileid="{}" duration="{}"/>"#, tile_id, duration).unwrap();
+                }
+                writeln!(tmx, r#"   </animation>"#).unwrap();
+            }
+            writeln!(tmx, r#"  </tile>"#).unwrap();
+        }
+        writeln!(tmx, r#" </tileset>"#).unwrap();
+    }
+}
+
+impl BuilderImage {
+    // Writes the image with its source relative to the map's folder
+    fn write(&self, tmx: &mut String, map_folder: &Path, indent: usize) {
+        let mut source = PathBuf::new();
+        for _ in map_folder.components() {
+            source.push("..");
+        }
+        source.push(&self.source);
+        let source = source.to_string_lossy().replace('\\', "/");
+        writeln!(tmx, r#"{}<image source="{}" width="{}" height="{}"/>"#, " ".repeat(indent), escape(&source), self.size.x, self.size.y).unwrap();
+    }
+}
+
+impl BuilderLayerTile {
+    fn gid(&self, first_gid: u32) -> u32 {
+        let mut gid = first_gid + self.id;
+        if self.flip_h { gid |= FLIPPED_HORIZONTALLY; }
+        if self.flip_v { gid |= FLIPPED_VERTICALLY; }
+        if self.flip_d { gid |= FLIPPED_DIAGONALLY; }
+        gid
+    }
+}
+
+impl BuilderObjectLayer {
+    // Writes the layer, numbering its objects from the next object id of the map
+    fn write(&self, tmx: &mut String, id: u32, next_object_id: &mut u32, indent: usize) {
+        let pad = " ".repeat(indent);
+        writeln!(tmx, r#"{}<objectgroup id="{}" name="{}">"#, pad, id, escape(&self.name)).unwrap();
+        for object in &self.objects {
+            let (x, y) = (object.position.x, object.position.y);
+            let attributes = format!(r#"id="{}" name="{}" type="{}" x="{}" y="{}""#, next_object_id, escape(&object.name), escape(&object.typ), x, y);
+            *next_object_id += 1;
+            if object.size == Vec2::ZERO {
+                writeln!(tmx, r#"{} <object {}>"#, pad, attributes).unwrap();
+                writeln!(tmx, r#"{}  <point/>"#, pad).unwrap();
+            }
+            else {
+                writeln!(tmx, r#"{} <object {} width="{}" height="{}">"#, pad, attributes, object.size.x, object.size.y).unwrap();
+            }
+            write_properties(tmx, &object.properties, indent + 2);
+            writeln!(tmx, r#"{} </object>"#, pad).unwrap();
+        }
+        writeln!(tmx, r#"{}</objectgroup>"#, pad).unwrap();
+    }
+}
+
+// Number of tile types in the meta tileset written by MapBuilder, which holds one tile per type
+fn meta_tile_count() -> u32 {
+    TileType::all().count() as u32
+}
+
+// Copies the objects of an object layer
+fn read_object_layer(name: &str, object_layer: &ObjectLayer) -> BuilderObjectLayer {
+    let objects = object_layer
+        .objects()
+        .map(|object| BuilderObject {
+            name: object.name.clone(),
+            typ: object.user_type.clone(),
+            position: Vec2::new(object.x, object.y),
+            size: match object.shape {
+                ObjectShape::Rect { width, height } => Vec2::new(width, height),
+                ObjectShape::Ellipse { width, height } => Vec2::new(width, height),
+                _ => Vec2::ZERO
+            },
+            properties: object.properties.clone()
+        })
+        .collect();
+    BuilderObjectLayer { name: name.to_owned(), objects }
+}
+
+// Writes custom properties, sorted by name so that output is stable
+fn write_properties(tmx: &mut String, properties: &Properties, indent: usize) {
+    if properties.is_empty() {
+        return;
+    }
+    let pad = " ".repeat(indent);
+    let mut names: Vec<&String> = properties.keys().collect();
+    names.sort();
+    writeln!(tmx, "{}<properties>", pad).unwrap();
+    for name in names {
+        let (typ, value) = match &properties[name] {
+            PropertyValue::BoolValue(value) => (Some("bool"), value.to_string()),
+            PropertyValue::FloatValue(value) => (Some("float"), value.to_string()),
+            PropertyValue::IntValue(value) => (Some("int"), value.to_string()),
+            PropertyValue::ColorValue(color) => (
+                Some("color"),
+                format!("#{:02x}{:02x}{:02x}{:02x}", color.alpha, color.red, color.green, color.blue)
+            ),
+            PropertyValue::StringValue(value) => (None, value.clone()),
+            PropertyValue::FileValue(value) => (Some("file"), value.clone()),
+            PropertyValue::ObjectValue(value) => (Some("object"), value.to_string())
+        };
+        match typ {
+            Some(typ) => writeln!(tmx, r#"{} <property name="{}" type="{}" value="{}"/>"#, pad, escape(name), typ, escape(&value)).unwrap(),
+            None => writeln!(tmx, r#"{} <property name="{}" value="{}"/>"#, pad, escape(name), escape(&value)).unwrap()
+        }
+    }
+    writeln!(tmx, "{}</properties>", pad).unwrap();
+}
+
+// Escapes text for use in an XML attribute
+fn escape(text: &str) -> String {
+    text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+
+#[test]
+fn test_map_builder_climbs_wall() {
+    use crate::physics::{Coords, TerrainPiece};
+
+    // Single column, with a wall between two floors
+    let mut builder = MapBuilder::new("wall.tmx", UVec2::new(1, 3), UVec2::new(16, 16));
+    let tileset = builder.add_tileset("cliffs", "tilesets/cliffs.png", UVec2::new(64, 64));
+    let ground = builder.add_group("ground", 0, false);
+    builder.set_tile_type(ground, IVec2::new(0, 1), TileType::Wall);
+    for y in 0..3 {
+        builder.set_tile(ground, 0, IVec2::new(0, y), tileset, y as u32);
+    }
+    let map_config = MapConfig::default();
+    let (current_map, current_map_graphics) = builder.build(&map_config).unwrap();
+
+    // Bottom floor sits at z = 0, and the floor on top of the wall is a tile higher and farther
+    let piece_at = |x, y, z| current_map.terrain.get(Coords::new(x, y, z)).copied().unwrap_or(TerrainPiece::Empty);
+    assert_eq!(TerrainPiece::Cuboid, piece_at(0, -1, 0));
+    assert_eq!(TerrainPiece::Empty, piece_at(0, 0, 0));
+    assert_eq!(TerrainPiece::Cuboid, piece_at(0, 0, -1));
+    assert_eq!(TerrainPiece::Cuboid, piece_at(0, -1, -1));
+    assert_eq!(TerrainPiece::Empty, piece_at(0, 1, -1));
+    assert_eq!(Some(&TileType::Wall), current_map.meta_tiles.get(&IVec2::new(0, 1)));
+
+    // Every tile has a quad in the tileset's chunks
+    let vertex_count: usize = current_map_graphics.chunks
+        .iter()
+        .filter(|(key, _)| key.tileset_handle_index == tileset + 1)
+        .map(|(_, chunk)| chunk.positions.len())
+        .sum();
+    assert_eq!(3 * 4, vertex_count);
+}
+
+#[test]
+fn test_map_builder_tmx_roundtrip() {
+    use crate::map::{GeneratorTiles, MapGenerator};
+
+    let map_config = MapConfig::default();
+    let roundtrip = |builder: &MapBuilder| {
+        let tiled_map = builder.build_tiled_map().unwrap();
+        MapBuilder::from_tiled_map(builder.name(), &tiled_map, "").unwrap()
+    };
+
+    // Exporting a generated map and loading it again gives the same terrain
+    let generator = MapGenerator {
+        tiles: Some(GeneratorTiles {
+            image: "tilesets/generated.png".to_owned(),
+            image_size: UVec2::new(64, 64),
+            floor: 0,
+            wall: 1,
+            slope: 2,
+            lip: 3
+        }),
+        ..MapGenerator::new(3, UVec2::new(32, 32))
+    };
+    let generated = generator.generate();
+    let exported = roundtrip(&generated);
+    let (generated_map, _) = generated.build(&map_config).unwrap();
+    let (exported_map, _) = exported.build(&map_config).unwrap();
+    assert!(generated_map.terrain == exported_map.terrain);
+    assert_eq!(generated_map.meta_tiles, exported_map.meta_tiles);
+    assert_eq!(exported.to_tmx(), roundtrip(&exported).to_tmx());
+
+    // Edited tiles are exported as meta tiles
+    let mut walled = MapBuilder::new("edited.tmx", UVec2::new(1, 3), UVec2::new(16, 16));
+    let ground = walled.add_group("ground", 0, false);
+    let mut edited = walled.clone();
+    walled.set_tile_type(ground, IVec2::new(0, 1), TileType::Wall);
+    let mut edited_tiles = HashMap::default();
+    edited_tiles.insert(IVec2::new(0, 1), TileType::Wall);
+    edited.set_edited_tiles(&edited_tiles);
+    let (walled_map, _) = walled.build(&map_config).unwrap();
+    let (edited_map, _) = roundtrip(&edited).build(&map_config).unwrap();
+    assert!(walled_map.terrain == edited_map.terrain);
+
+    // Header holds ids past the last layer and object written.
+    // Group, meta layer and object layer take layer ids 1 to 3, and both objects take object ids 1 and 2.
+    let object = BuilderObject {
+        name: "sign".to_owned(),
+        typ: "Sign".to_owned(),
+        position: Vec2::new(8.0, 8.0),
+        size: Vec2::ZERO,
+        properties: Properties::new()
+    };
+    walled.add_object(ground, "objects", object.clone());
+    walled.add_object(ground, "objects", object);
+    let header = |builder: &MapBuilder| builder.to_tmx().lines().nth(1).unwrap().to_owned();
+    assert!(header(&walled).ends_with(r#"nextlayerid="4" nextobjectid="3">"#));
+    assert_eq!(header(&walled), header(&roundtrip(&walled)));
+}
+
+#[test]
+fn test_map_builder_meta_tiles_roundtrip() {
+
+    // One meta tile of every type, in a row
+    let tile_types: Vec<TileType> = TileType::all().collect();
+    assert_eq!(Some(&TileType::Floor), tile_types.first());
+    assert_eq!(Some(&TileType::Step), tile_types.last());
+    let mut builder = MapBuilder::new("meta.tmx", UVec2::new(tile_types.len() as u32, 1), UVec2::new(16, 16));
+    let ground = builder.add_group("ground", 0, false);
+    for (x, tile_type) in tile_types.iter().enumerate() {
+        assert_eq!(Some(*tile_type), TileType::from_str(tile_type.name()));
+        builder.set_tile_type(ground, IVec2::new(x as i32, 0), *tile_type);
+    }
+
+    // Every type is read back from the exported meta tileset
+    let tiled_map = builder.build_tiled_map().unwrap();
+    let exported = MapBuilder::from_tiled_map(builder.name(), &tiled_map, "").unwrap();
+    assert_eq!(builder.groups[0].meta_tiles, exported.groups[0].meta_tiles);
+}
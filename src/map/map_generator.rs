@@ -0,0 +1,208 @@
+use std::ops::Range;
+
+use bevy::prelude::*;
+
+use crate::map::{MapBuilder, TileType};
+
+/// Generates maps of floors, cliffs and slopes from a seed.
+/// The same settings and seed always generate the same map, on every platform, so a server and its clients
+/// only need to agree on the settings to end up with the same terrain.
+///
+/// Maps are made of plateaus standing on flat ground. The front of a plateau is a cliff, part of which may be a slope leading up to its top.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapGenerator {
+    pub seed: u64,
+    /// Size of the map in tiles
+    pub size: UVec2,
+    /// Size of a tile in pixels
+    pub tile_size: UVec2,
+    /// Number of plateaus to try placing. Plateaus that don't fit without touching another are skipped.
+    pub plateaus: u32,
+    /// Largest width and depth of the top of a plateau, in tiles
+    pub max_plateau_size: UVec2,
+    /// Tallest a plateau can be, in tiles
+    pub max_height: u32,
+    /// Chance that a plateau has a slope up its front, in percent
+    pub slope_chance: u32,
+    /// Tiles to draw the map with. Without them, the map has terrain but no graphics.
+    pub tiles: Option<GeneratorTiles>
+}
+
+/// Tiles of a single image tileset that a [`MapGenerator`] draws with
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorTiles {
+    /// Path of the tileset image, relative to the asset folder
+    pub image: String,
+    pub image_size: UVec2,
+    pub floor: u32,
+    pub wall: u32,
+    pub slope: u32,
+    pub lip: u32
+}
+
+impl MapGenerator {
+
+    /// Generator with default settings
+    pub fn new(seed: u64, size: UVec2) -> Self {
+        Self {
+            seed,
+            size,
+            tile_size: UVec2::new(16, 16),
+            plateaus: size.x * size.y / 64,
+            max_plateau_size: UVec2::new(8, 6),
+            max_height: 3,
+            slope_chance: 50,
+            tiles: None
+        }
+    }
+
+    /// Generates the map. Run the result through [`MapBuilder::build`] to get its terrain and graphics.
+    pub fn generate(&self) -> MapBuilder {
+        let mut rng = Rng::new(self.seed);
+        let (width, height) = (self.size.x, self.size.y);
+        let mut tile_types = vec![TileType::Floor; (width * height) as usize];
+        let mut occupied = vec![false; (width * height) as usize];
+        let index = |x: u32, y: u32| (y * width + x) as usize;
+
+        for _ in 0..self.plateaus {
+
+            // Picks the shape of the plateau.
+            // Its footprint runs from the lip at the back to the bottom of the slope at the front, which is twice as deep as the plateau is tall.
+            let plateau_width = rng.range(2..self.max_plateau_size.x.max(2) + 1);
+            let plateau_depth = rng.range(1..self.max_plateau_size.y.max(1) + 1);
+            let plateau_height = rng.range(1..self.max_height.max(1) + 1);
+            let has_slope = rng.range(0..100) < self.slope_chance;
+            let rows = 1 + plateau_depth + 2 * plateau_height;
+            if plateau_width + 2 > width || rows + 2 > height {
+                continue;
+            }
+
+            // Picks where it goes, keeping a tile of flat ground around it
+            let x0 = rng.range(1..width - plateau_width);
+            let y0 = rng.range(1..height - rows);
+            let is_free = (x0 - 1..x0 + plateau_width + 1)
+                .all(|x| (y0 - 1..y0 + rows + 1).all(|y| !occupied[index(x, y)]));
+            if !is_free {
+                continue;
+            }
+            for x in x0..x0 + plateau_width {
+                for y in y0..y0 + rows {
+                    occupied[index(x, y)] = true;
+                }
+            }
+
+            // Picks which columns of the front are a slope
+            let slope_columns = if has_slope {
+                let slope_width = rng.range(1..plateau_width + 1);
+                let slope_x = rng.range(x0..x0 + plateau_width - slope_width + 1);
+                slope_x..slope_x + slope_width
+            }
+            else {
+                0..0
+            };
+
+            // Writes columns from the back: a lip, the top, then the cliff or slope beneath it.
+            // Cliffs are half as deep as slopes, so the ground in front of them fills the rest.
+            let face_y = y0 + 1 + plateau_depth;
+            for x in x0..x0 + plateau_width {
+                tile_types[index(x, y0)] = TileType::LipN;
+                if slope_columns.contains(&x) {
+                    for y in face_y..face_y + 2 * plateau_height {
+                        tile_types[index(x, y)] = TileType::Slope;
+                    }
+                }
+                else {
+                    for y in face_y..face_y + plateau_height {
+                        tile_types[index(x, y)] = TileType::Wall;
+                    }
+                }
+            }
+        }
+
+        // Places meta tiles and terrain tiles
+        let mut builder = MapBuilder::new(format!("generated-{}.tmx", self.seed), self.size, self.tile_size);
+        let ground = builder.add_group("ground", 0, false);
+        let tileset = self.tiles
+            .as_ref()
+            .map(|tiles| builder.add_tileset("terrain", tiles.image.clone(), tiles.image_size));
+        for y in 0..height {
+            for x in 0..width {
+                let tile = IVec2::new(x as i32, y as i32);
+                let tile_type = tile_types[index(x, y)];
+                if tile_type != TileType::Floor {
+                    builder.set_tile_type(ground, tile, tile_type);
+                }
+                if let (Some(tiles), Some(tileset)) = (&self.tiles, tileset) {
+                    let tile_id = match tile_type {
+                        TileType::Wall => tiles.wall,
+                        TileType::Slope => tiles.slope,
+                        TileType::LipN => tiles.lip,
+                        _ => tiles.floor
+                    };
+                    builder.set_tile(ground, 0, tile, tileset, tile_id);
+                }
+            }
+        }
+        builder
+    }
+}
+
+// SplitMix64 random number generator.
+// Kept local rather than taken from a crate, so that the numbers a seed produces can never change underneath us.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Number within a range, which must not be empty
+    fn range(&mut self, range: Range<u32>) -> u32 {
+        let len = (range.end - range.start) as u64;
+        range.start + (self.next_u64() % len) as u32
+    }
+}
+
+
+#[test]
+fn test_map_generator() {
+    use crate::map::MapConfig;
+    use crate::physics::TerrainPiece;
+
+    let map_config = MapConfig::default();
+    let generator = MapGenerator {
+        tiles: Some(GeneratorTiles {
+            image: "tilesets/generated.png".to_owned(),
+            image_size: UVec2::new(64, 64),
+            floor: 0,
+            wall: 1,
+            slope: 2,
+            lip: 3
+        }),
+        ..MapGenerator::new(7, UVec2::new(48, 48))
+    };
+
+    // Same seed, same map
+    assert_eq!(generator.generate().to_tmx(), generator.generate().to_tmx());
+    let other = MapGenerator { seed: 8, ..generator.clone() };
+    assert_ne!(generator.generate().to_tmx(), other.generate().to_tmx());
+
+    // Every seed climbs without errors, and raises something above the ground
+    for seed in 0..16 {
+        let generator = MapGenerator { seed, ..generator.clone() };
+        let (current_map, current_map_graphics) = generator.generate().build(&map_config).unwrap();
+        let raised = current_map.terrain
+            .chunks()
+            .any(|(coords, pieces)| coords.y >= 0 && pieces.iter().any(|piece| *piece != TerrainPiece::Empty));
+        assert!(raised);
+        assert!(!current_map_graphics.chunks.is_empty());
+    }
+}
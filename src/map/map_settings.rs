@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use tiled::{Properties, PropertyValue};
+
+use crate::extensions::PathExt;
+use crate::map::MapLoadError;
+
+/// Settings of a map, read from its custom properties when it spawns.
+/// Colors can be color properties, or strings in Tiled's "#rrggbb" / "#aarrggbb" format.
+/// Properties that are missing keep their default value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapSettings {
+    /// Color the camera clears the screen with ("clear_color")
+    pub clear_color: Color,
+    /// Color of the directional light ("light_color")
+    pub light_color: Color,
+    /// Illuminance of the directional light in lux ("light_illuminance")
+    pub light_illuminance: f32,
+    /// Angle of the directional light below the horizon, in degrees ("light_angle")
+    pub light_angle: f32,
+    /// Angle of the directional light clockwise from north, in degrees ("light_yaw")
+    pub light_yaw: f32,
+    /// Overrides the gravity of the game while the map is spawned ("gravity")
+    pub gravity: Option<f32>,
+    /// Background music that loops while the map is spawned, relative to the asset folder ("music").
+    /// File properties are relative to the map file, like Tiled writes them, while string properties are relative to the asset folder.
+    pub music: Option<String>
+}
+
+impl Default for MapSettings {
+    fn default() -> Self {
+        Self {
+            clear_color: Color::GRAY,
+            light_color: Color::WHITE,
+            light_illuminance: 27500.0,
+            light_angle: 45.0,
+            light_yaw: 0.0,
+            gravity: None,
+            music: None
+        }
+    }
+}
+
+impl MapSettings {
+
+    /// Reads settings from the custom properties of a map, whose path is relative to the asset folder.
+    /// Fails if a property has the wrong type.
+    pub fn from_properties(properties: &Properties, map_path: impl AsRef<Path>) -> Result<Self, MapLoadError> {
+        let default = Self::default();
+        Ok(Self {
+            clear_color: get_color(properties, "clear_color")?.unwrap_or(default.clear_color),
+            light_color: get_color(properties, "light_color")?.unwrap_or(default.light_color),
+            light_illuminance: get_float(properties, "light_illuminance")?.unwrap_or(default.light_illuminance),
+            light_angle: get_float(properties, "light_angle")?.unwrap_or(default.light_angle),
+            light_yaw: get_float(properties, "light_yaw")?.unwrap_or(default.light_yaw),
+            gravity: get_float(properties, "gravity")?,
+            music: match properties.get("music") {
+                Some(PropertyValue::FileValue(path)) => {
+                    let map_folder = map_path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+                    Some(map_folder.join(path).normalize().to_string_lossy().into_owned())
+                }
+                Some(PropertyValue::StringValue(path)) => Some(path.clone()),
+                None => None,
+                Some(_) => return Err(invalid_property("music", "a file or a string"))
+            }
+        })
+    }
+
+    /// Direction the directional light points in
+    pub fn light_direction(&self) -> Vec3 {
+        let (angle, yaw) = (self.light_angle.to_radians(), self.light_yaw.to_radians());
+        Vec3::new(
+            yaw.sin() * angle.cos(),
+            -angle.sin(),
+            -yaw.cos() * angle.cos()
+        )
+    }
+}
+
+// Gets a float property. Ints are accepted too, since Tiled makes it easy to pick the wrong one.
+fn get_float(properties: &Properties, key: &str) -> Result<Option<f32>, MapLoadError> {
+    match properties.get(key) {
+        Some(PropertyValue::FloatValue(value)) => Ok(Some(*value)),
+        Some(PropertyValue::IntValue(value)) => Ok(Some(*value as f32)),
+        None => Ok(None),
+        Some(_) => Err(invalid_property(key, "a float"))
+    }
+}
+
+// Gets a color property, or a string property in Tiled's "#rrggbb" / "#aarrggbb" format
+fn get_color(properties: &Properties, key: &str) -> Result<Option<Color>, MapLoadError> {
+    match properties.get(key) {
+        Some(PropertyValue::ColorValue(color)) => Ok(Some(Color::rgba_u8(color.red, color.green, color.blue, color.alpha))),
+        Some(PropertyValue::StringValue(value)) => parse_hex_color(value)
+            .map(Some)
+            .ok_or_else(|| invalid_property(key, "a color")),
+        None => Ok(None),
+        Some(_) => Err(invalid_property(key, "a color"))
+    }
+}
+
+// Parses "#rrggbb" or "#aarrggbb"
+fn parse_hex_color(value: &str) -> Option<Color> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    let byte = |index: usize| u8::from_str_radix(hex.get(index*2..index*2+2)?, 16).ok();
+    match hex.len() {
+        6 => Some(Color::rgb_u8(byte(0)?, byte(1)?, byte(2)?)),
+        8 => Some(Color::rgba_u8(byte(1)?, byte(2)?, byte(3)?, byte(0)?)),
+        _ => None
+    }
+}
+
+fn invalid_property(key: &str, expected: &str) -> MapLoadError {
+    MapLoadError::MapProperty {
+        name: key.to_owned(),
+        message: format!("Must be {}", expected)
+    }
+}
+
+
+#[test]
+fn test_map_settings() {
+    let mut properties = Properties::new();
+    properties.insert("clear_color".to_owned(), PropertyValue::StringValue("#80ff0000".to_owned()));
+    properties.insert("light_illuminance".to_owned(), PropertyValue::IntValue(10000));
+    properties.insert("gravity".to_owned(), PropertyValue::FloatValue(0.5));
+    properties.insert("music".to_owned(), PropertyValue::FileValue("../music/cave.ogg".to_owned()));
+    let settings = MapSettings::from_properties(&properties, "maps/tmx/cave.tmx").unwrap();
+    assert_eq!(Color::rgba_u8(255, 0, 0, 128), settings.clear_color);
+    assert_eq!(Color::WHITE, settings.light_color);
+    assert_eq!(10000.0, settings.light_illuminance);
+    assert_eq!(Some(0.5), settings.gravity);
+    assert_eq!(Some("maps/music/cave.ogg".to_owned()), settings.music);
+    assert!(settings.light_direction().abs_diff_eq(Vec3::new(0.0, -1.0, -1.0).normalize(), 0.0001));
+
+    properties.insert("gravity".to_owned(), PropertyValue::BoolValue(true));
+    assert!(MapSettings::from_properties(&properties, "maps/tmx/cave.tmx").is_err());
+}
@@ -3,26 +3,50 @@ mod current_map;
 mod current_map_graphics;
 mod tile;
 mod traverse;
+mod object;
+mod error;
+mod tile_animation;
+mod loaded_map;
+mod baked_map;
+mod map_settings;
+mod vidya_world;
+mod chunk_streaming;
+mod terrain_edit;
+mod map_builder;
+mod map_generator;
 use std::iter::Iterator;
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use crate::game::GameState;
+use crate::game::{GameState, SystemLabels, run_if_tick_elapsed};
 use crate::camera::{GameCameraBundle, CameraTargetSettings};
-use crate::physics::{ Position, Velocity, Friction, Terrain };
+use crate::physics::{ Position, Velocity, Friction, Terrain, Gravity };
 use crate::extensions::*;
-use crate::screen::{LoadScreenEvent, ScreenLoadedEvent};
+use crate::screen::{LoadScreenEvent, ScreenLoadedEvent, ScreenLoadFailedEvent};
 
 use bevy::prelude::*;
 use bevy::asset::{ AssetServerSettings, LoadState };
 use bevy::reflect::TypeUuid;
-use bevy::render::mesh::Indices;
-use bevy::render::render_resource::PrimitiveTopology;
+use bevy::tasks::{ AsyncComputeTaskPool, Task };
+use futures_lite::future;
+use tiled::{Map, Tileset};
 
 pub use current_map::*;
 pub use current_map_graphics::*;
 pub use vidya_map::*;
 pub use tile::*;
 pub use traverse::*;
+pub use object::*;
+pub use error::*;
+pub use tile_animation::*;
+pub use loaded_map::*;
+pub use baked_map::*;
+pub use map_settings::*;
+pub use vidya_world::*;
+pub use chunk_streaming::*;
+pub use terrain_edit::*;
+pub use map_builder::*;
+pub use map_generator::*;
 
 /// Screen type for maps
 #[derive(Debug, TypeUuid)]
@@ -34,36 +58,72 @@ impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
         app
             .add_event::<MapSpawnedEvent>()
+            .add_event::<MapUnloadedEvent>()
+            .add_event::<MapObjectEvent>()
+            .add_event::<MapLoadFailedEvent>()
+            .add_event::<EditTerrainEvent>()
+            .add_event::<TerrainChangedEvent>()
             .add_asset::<VidyaMap>()
+            .add_asset::<VidyaTileset>()
+            .add_asset::<BakedMap>()
+            .add_asset::<VidyaWorld>()
+            .init_resource::<MapLoadErrors>()
             .init_asset_loader::<VidyaMapLoader>()
-            .insert_resource(MapConfig {
-                chunk_size: Vec3::new(
-                    (16*16) as f32,
-                    (16*16) as f32,
-                    (16*16) as f32
-                ),
-                flip_y: false
-            })
+            .init_asset_loader::<VidyaTilesetLoader>()
+            .init_asset_loader::<BakedMapLoader>()
+            .init_asset_loader::<VidyaWorldLoader>()
+            .init_resource::<TileAnimationClock>()
+            .init_resource::<MapConfig>()
             // Listens for "LoadScreenEvent" and kicks off map loading
             .add_system_set(SystemSet::on_update(GameState::GameRunning)
                 .with_system(handle_load_event)
             )
 
+            // Rebuilds the spawned map when its files change
+            .add_system_set(SystemSet::on_update(GameState::GameRunning)
+                .with_system(reload_modified_map)
+            )
+
             // Halts further progress until map is loaded.
             // When map is loaded, kicks off the graphics loading.
             .add_system_set(SystemSet::on_update(GameState::MapLoadingFile)
                 .with_system(map_finish_loading)
+                .with_system(baked_map_finish_loading)
             )
 
-            // Constructs map based on the TiledMap loaded.
+            // Constructs map based on the TiledMap loaded, off the main thread.
             .add_system_set(SystemSet::on_enter(GameState::MapConstructing)
                 .with_system(map_construct)
             )
+            .add_system_set(SystemSet::on_update(GameState::MapConstructing)
+                .with_system(map_finish_constructing)
+            )
 
             // Spawns map entities (the map itself, not the player, enemies, etc.)
             .add_system_set(SystemSet::on_update(GameState::MapSpawning)
                 .with_system(map_spawn_entities)
             )
+
+            // Spawns and despawns chunks as the camera and physics entities move around.
+            // Terrain streams in before collision, so that bodies never collide with missing terrain.
+            .add_system_set(SystemSet::on_update(GameState::GameRunning)
+                .with_system(stream_map_chunks)
+                .with_system(stream_terrain_chunks.before(SystemLabels::PhysicsCollide))
+            )
+
+            // Applies terrain edits sent by game logic before anything collides with the terrain
+            .add_system_set(SystemSet::on_update(GameState::GameRunning)
+                .with_system(apply_terrain_edits
+                    .after(SystemLabels::Logic)
+                    .before(SystemLabels::PhysicsCollide)
+                )
+            )
+
+            // Advances animated tiles once per tick
+            .add_system_set(SystemSet::on_update(GameState::GameRunning)
+                .with_run_criteria(run_if_tick_elapsed)
+                .with_system(animate_tiles)
+            )
         ;
     }
 }
@@ -75,6 +135,11 @@ fn handle_load_event(
     mut load_events: EventReader<LoadScreenEvent>,
     mut state: ResMut<State<GameState>>,
     asset_server: Res<AssetServer>,
+    loaded_map: Option<Res<LoadedMap>>,
+    map_entities: Query<Entity, With<MapEntity>>,
+    music: Option<Res<MapMusic>>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    mut unloaded_writer: EventWriter<MapUnloadedEvent>,
     mut commands: Commands
 ) {
     log::debug!("(SYSTEM) map_listen");
@@ -84,38 +149,185 @@ fn handle_load_event(
         Some(event) => event,
         None => return
     };
+
+    // Unloads the spawned map, if any
+    if let Some(loaded_map) = loaded_map {
+        for entity in &map_entities {
+            commands.entity(entity).despawn_recursive();
+        }
+        commands.remove_resource::<LoadedMap>();
+        commands.remove_resource::<StreamedMap>();
+        unloaded_writer.send(MapUnloadedEvent(loaded_map.name.clone()));
+    }
+    if let Some(music) = music {
+        if let Some(sink) = audio_sinks.get(&music.0) {
+            sink.stop();
+        }
+        commands.remove_resource::<MapMusic>();
+    }
     if !event.0.is_screen_type(MapScreenType) {
         return;
     }
 
-    // Begins loading map and stores the handle for later use
+    // Begins loading map and keeps track of the map that is loading.
+    // Baked maps skip construction entirely, and worlds load every map in them.
     let map_file = event.0.name();
-    let map_handle = asset_server.load(map_file);
+    let current_map = if map_file.ends_with(".vmap") {
+        CurrentMap {
+            baked_handle: Some(asset_server.load(map_file)),
+            ..CurrentMap::new(map_file, Handle::default())
+        }
+    }
+    else if map_file.ends_with(".world") {
+        CurrentMap {
+            world_handle: Some(asset_server.load(map_file)),
+            ..CurrentMap::new(map_file, Handle::default())
+        }
+    }
+    else {
+        CurrentMap::new(map_file, asset_server.load(map_file))
+    };
+    commands.insert_resource(current_map);
+
+    // Goes to loading state
+    state.push(GameState::MapLoadingFile).unwrap()
+}
+
+// 1) Listens for changes to the spawned map's TMX file, or to the tilesets it depends on.
+//    Maps loaded from a world also listen for changes to the world file, and to every map in it.
+// 2) Begins rebuilding the map in place
+// 3) Goes to LoadingMap state
+// Changes are only noticed if the asset server is watching for them.
+fn reload_modified_map(
+    loaded_map: Option<Res<LoadedMap>>,
+    vidya_maps: Res<Assets<VidyaMap>>,
+    vidya_worlds: Res<Assets<VidyaWorld>>,
+    mut map_events: EventReader<AssetEvent<VidyaMap>>,
+    mut world_events: EventReader<AssetEvent<VidyaWorld>>,
+    mut tileset_events: EventReader<AssetEvent<VidyaTileset>>,
+    mut load_events: EventReader<LoadScreenEvent>,
+    asset_server: Res<AssetServer>,
+    mut state: ResMut<State<GameState>>,
+    mut commands: Commands
+) {
+    log::debug!("(SYSTEM) reload_modified_map");
+
+    // Gets the spawned map, if any, along with the maps it is made of
+    let loaded_map = match loaded_map {
+        Some(loaded_map) => loaded_map,
+        None => return
+    };
+    let map_handles: Vec<&Handle<VidyaMap>> = match &loaded_map.world_handle {
+        Some(world_handle) => match vidya_worlds.get(world_handle) {
+            Some(world) => world.maps.iter().map(|world_map| &world_map.map_handle).collect(),
+            None => return
+        },
+        None => vec![&loaded_map.map_handle]
+    };
+
+    // No point in reloading a map that is about to be despawned
+    if load_events.iter().next().is_some() {
+        return;
+    }
+
+    // A modified tileset reloads the map files that use it, which in turn get reported as modified maps
+    for event in tileset_events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            let modified_maps = map_handles
+                .iter()
+                .filter(|map_handle| vidya_maps.get(**map_handle).map_or(false, |vidya_map| vidya_map.tilesets.contains(handle)));
+            for map_handle in modified_maps {
+                if let Some(path) = asset_server.get_handle_path(*map_handle) {
+                    asset_server.reload_asset(path);
+                }
+            }
+        }
+    }
 
-    // Creates current map resource and keeps track of the map that is loading
+    // Rebuilds the map if it, its world or any map in its world was modified
+    let mut map_modified = false;
+    for event in map_events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            map_modified |= map_handles.contains(&handle);
+        }
+    }
+    for event in world_events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            map_modified |= loaded_map.world_handle.as_ref() == Some(handle);
+        }
+    }
+    if !map_modified {
+        return;
+    }
+    log::info!("Map '{}' changed, reloading", loaded_map.name);
+    // Tiles edited since the map spawned stay edited
     commands.insert_resource(CurrentMap {
-        name: map_file.to_string(),
-        map_handle,
-        terrain: Terrain::new(Vec3::new(16.0, 16.0, 16.0), UVec3::new(16, 16, 16))
+        reloading: true,
+        world_handle: loaded_map.world_handle.clone(),
+        meta_overrides: loaded_map.edited_tiles.clone(),
+        ..CurrentMap::new(loaded_map.name.clone(), loaded_map.map_handle.clone())
     });
-
-    // Goes to loading state
     state.push(GameState::MapLoadingFile).unwrap()
 }
 
 // 1) When in LoadingMapState, checks if map finished loading
-// 2) If so, loads tileset images, sets counter to 1 and goes to FiringEvents state
+// 2) If so, begins loading tileset images
+// 3) When tileset images finish loading, packs image collections into atlases and goes to MapConstructing state
 fn map_finish_loading(
     asset_server: Res<AssetServer>,
     current_map: Res<CurrentMap>,
+    current_map_graphics: Option<ResMut<CurrentMapGraphics>>,
     vidya_maps: Res<Assets<VidyaMap>>,
+    vidya_worlds: Res<Assets<VidyaWorld>>,
+    mut images: ResMut<Assets<Image>>,
     map_config: Res<MapConfig>,
     asset_server_settings: Res<AssetServerSettings>,
+    load_errors: Res<MapLoadErrors>,
     mut app_state: ResMut<State<GameState>>,
+    mut failed_writer: EventWriter<MapLoadFailedEvent>,
+    mut screen_failed_writer: EventWriter<ScreenLoadFailedEvent>,
     mut commands: Commands
 ) {
     log::debug!("(SYSTEM) map_finish_loading");
-    let load_state = asset_server.get_load_state(&current_map.map_handle);
+
+    // Baked maps are handled by baked_map_finish_loading
+    if current_map.baked_handle.is_some() {
+        return;
+    }
+
+    // Waits for tileset images if the map itself has already loaded
+    if let Some(mut current_map_graphics) = current_map_graphics {
+        match current_map_graphics.get_load_state(&asset_server) {
+            LoadState::Loaded => {
+                let tilesets: Vec<Arc<Tileset>> = placed_maps(&current_map.map_handle, current_map.world_handle.as_ref(), &vidya_maps, &vidya_worlds)
+                    .unwrap()
+                    .iter()
+                    .flat_map(|(tiled_map, _)| tiled_map.tilesets().iter().cloned())
+                    .collect();
+                match current_map_graphics.build_atlases(&tilesets, &mut images) {
+                    Ok(()) => app_state.set(GameState::MapConstructing).unwrap(),
+                    Err(error) => abort_map_loading(&current_map, error, &mut app_state, &mut failed_writer, &mut screen_failed_writer, &mut commands)
+                }
+            }
+            LoadState::Failed => {
+                let path = current_map_graphics
+                    .tileset_handles
+                    .iter()
+                    .flatten()
+                    .chain(current_map_graphics.collection_handles.iter().flatten().map(|(_, handle)| handle))
+                    .find(|handle| asset_server.get_load_state(*handle) == LoadState::Failed)
+                    .and_then(|handle| asset_server.get_handle_path(handle))
+                    .map(|path| path.path().display().to_string())
+                    .unwrap_or_default();
+                let error = MapLoadError::AssetFailed { path };
+                abort_map_loading(&current_map, error, &mut app_state, &mut failed_writer, &mut screen_failed_writer, &mut commands);
+            }
+            _ => {}
+        }
+        return;
+    }
+
+    let load_state = map_load_state(&current_map, &asset_server, &vidya_worlds);
     match load_state {
         LoadState::Loaded => {
 
@@ -125,13 +337,13 @@ fn map_finish_loading(
                 ..Default::default()
             };
 
-            // Begins loading map graphics asynchronously
-            let tiled_map = &vidya_maps
-                .get(&current_map.map_handle)
-                .unwrap()
-                .tiled_map;
+            // Begins loading map graphics asynchronously.
+            // Tilesets made of a single image load that image, and image collection tilesets load the image of every tile.
+            // The tilesets of every map in a world are listed one after another.
+            let placed_maps = placed_maps(&current_map.map_handle, current_map.world_handle.as_ref(), &vidya_maps, &vidya_worlds).unwrap();
             let asset_folder = PathBuf::from(&asset_server_settings.asset_folder);
-            for tileset in tiled_map.tilesets() {
+            for tileset in placed_maps.iter().flat_map(|(tiled_map, _)| tiled_map.tilesets()) {
+                let mut tile_handles = Vec::new();
                 if let Some(image) = &tileset.image {
                     let image_source = image.source.relativize(&asset_folder);
                     let image_handle = asset_server.load(image_source.as_path());
@@ -140,123 +352,280 @@ fn map_finish_loading(
                         .push(Some(image_handle));
                 }
                 else {
+                    for (tile_id, tile) in tileset.tiles() {
+                        if let Some(image) = &tile.image {
+                            let image_source = image.source.relativize(&asset_folder);
+                            tile_handles.push((tile_id, asset_server.load(image_source.as_path())));
+                        }
+                    }
                     current_map_graphics
                         .tileset_handles
                         .push(None);
                 }
+                current_map_graphics.collection_handles.push(tile_handles);
+                current_map_graphics.tileset_atlases.push(None);
             }
 
-            // Goes to "constructing" state
+            // Waits for images before constructing
             commands.insert_resource(current_map_graphics);
-            app_state.set(GameState::MapConstructing).unwrap();
         }
         LoadState::Failed => {
-            panic!("Failed to load map file");
+            let error = map_load_error(&current_map, &asset_server, &vidya_worlds, &load_errors);
+            abort_map_loading(&current_map, error, &mut app_state, &mut failed_writer, &mut screen_failed_writer, &mut commands);
         }
         _ => {}
     }
 }
 
-// Constructs map
-fn map_construct(
+// Reason the map, or the world or one of the maps in it, failed to load
+fn map_load_error(
+    current_map: &CurrentMap,
+    asset_server: &AssetServer,
+    vidya_worlds: &Assets<VidyaWorld>,
+    load_errors: &MapLoadErrors
+) -> MapLoadError {
+    let mut handle_ids = match &current_map.world_handle {
+        Some(world_handle) => vec![world_handle.id],
+        None => vec![current_map.map_handle.id]
+    };
+    if let Some(world) = current_map.world_handle.as_ref().and_then(|world_handle| vidya_worlds.get(world_handle)) {
+        handle_ids.extend(world.maps.iter().map(|world_map| world_map.map_handle.id));
+    }
+    handle_ids
+        .into_iter()
+        .find(|handle_id| asset_server.get_load_state(*handle_id) == LoadState::Failed)
+        .map(|handle_id| load_errors.asset_failed(asset_server, handle_id))
+        .unwrap_or_else(|| MapLoadError::AssetFailed { path: current_map.name.clone() })
+}
+
+// Load state of the map, or of a world and every map in it
+fn map_load_state(current_map: &CurrentMap, asset_server: &AssetServer, vidya_worlds: &Assets<VidyaWorld>) -> LoadState {
+    let world_handle = match &current_map.world_handle {
+        Some(world_handle) => world_handle,
+        None => return asset_server.get_load_state(&current_map.map_handle)
+    };
+    match asset_server.get_load_state(world_handle) {
+        LoadState::Loaded => {
+            let world = vidya_worlds.get(world_handle).unwrap();
+            asset_server.get_group_load_state(world.maps.iter().map(|world_map| world_map.map_handle.id))
+        }
+        load_state => load_state
+    }
+}
+
+// Tiled maps that make up a map or a world, along with where they are placed.
+// None if they have not loaded yet.
+fn placed_maps(
+    map_handle: &Handle<VidyaMap>,
+    world_handle: Option<&Handle<VidyaWorld>>,
+    vidya_maps: &Assets<VidyaMap>,
+    vidya_worlds: &Assets<VidyaWorld>
+) -> Option<Vec<(Arc<Map>, MapPlacement)>> {
+    match world_handle {
+        Some(world_handle) => vidya_worlds.get(world_handle)?.placed_maps(vidya_maps),
+        None => {
+            let tiled_map = vidya_maps.get(map_handle)?.tiled_map.clone();
+            let placement = MapPlacement::new(&tiled_map);
+            Some(vec![(tiled_map, placement)])
+        }
+    }
+}
+
+// 1) When in LoadingMapState, checks if the baked map finished loading
+// 2) If so, stages its contents and begins loading tileset images
+// 3) When tileset images finish loading, goes to MapSpawning state
+fn baked_map_finish_loading(
+    asset_server: Res<AssetServer>,
     mut current_map: ResMut<CurrentMap>,
-    mut current_map_graphics: ResMut<CurrentMapGraphics>,
-    vidya_map: Res<Assets<VidyaMap>>,
+    current_map_graphics: Option<Res<CurrentMapGraphics>>,
+    baked_maps: Res<Assets<BakedMap>>,
+    mut images: ResMut<Assets<Image>>,
+    load_errors: Res<MapLoadErrors>,
     mut app_state: ResMut<State<GameState>>,
-    map_config: Res<MapConfig>
+    mut failed_writer: EventWriter<MapLoadFailedEvent>,
+    mut screen_failed_writer: EventWriter<ScreenLoadFailedEvent>,
+    mut commands: Commands
+) {
+    log::debug!("(SYSTEM) baked_map_finish_loading");
+
+    let baked_handle = match &current_map.baked_handle {
+        Some(baked_handle) => baked_handle.clone(),
+        None => return
+    };
+
+    // Waits for tileset images if the baked map itself has already loaded.
+    // Images stored as pixels were added directly, and have no load state to wait on.
+    if let Some(current_map_graphics) = current_map_graphics {
+        let image_handles: Vec<&Handle<Image>> = current_map_graphics
+            .tileset_handles
+            .iter()
+            .flatten()
+            .filter(|handle| asset_server.get_handle_path(*handle).is_some())
+            .collect();
+        match asset_server.get_group_load_state(image_handles.iter().map(|handle| handle.id)) {
+            LoadState::Loaded => app_state.set(GameState::MapSpawning).unwrap(),
+            LoadState::Failed => {
+                let path = image_handles
+                    .iter()
+                    .find(|handle| asset_server.get_load_state(**handle) == LoadState::Failed)
+                    .and_then(|handle| asset_server.get_handle_path(*handle))
+                    .map(|path| path.path().display().to_string())
+                    .unwrap_or_default();
+                let error = MapLoadError::AssetFailed { path };
+                abort_map_loading(&current_map, error, &mut app_state, &mut failed_writer, &mut screen_failed_writer, &mut commands);
+            }
+            _ => {}
+        }
+        return;
+    }
+
+    match asset_server.get_load_state(&baked_handle) {
+        LoadState::Loaded => {
+            let baked_map = baked_maps.get(&baked_handle).unwrap();
+            let current_map_graphics = baked_map.stage(&mut current_map, &asset_server, &mut images);
+            commands.insert_resource(current_map_graphics);
+        }
+        LoadState::Failed => {
+            let error = load_errors.asset_failed(&asset_server, baked_handle.id);
+            abort_map_loading(&current_map, error, &mut app_state, &mut failed_writer, &mut screen_failed_writer, &mut commands);
+        }
+        _ => {}
+    }
+}
+
+// Begins constructing the map on the async compute task pool.
+// The staging resources are handed to the task, and handed back when it finishes.
+fn map_construct(
+    current_map: Res<CurrentMap>,
+    current_map_graphics: Res<CurrentMapGraphics>,
+    vidya_maps: Res<Assets<VidyaMap>>,
+    vidya_worlds: Res<Assets<VidyaWorld>>,
+    map_config: Res<MapConfig>,
+    mut commands: Commands
 ) {
     log::debug!("(SYSTEM) map_construct");
     
-    // Gets tiled map
-    let tiled_map = &vidya_map
-        .get(&current_map.map_handle)
-        .unwrap()
-        .tiled_map;
+    // Gets tiled maps
+    let placed_maps = placed_maps(&current_map.map_handle, current_map.world_handle.as_ref(), &vidya_maps, &vidya_worlds).unwrap();
 
     // Traverses the map and populates both current_map and current_map_graphics
-    process_tiled_map(
-        &tiled_map,
-        map_config.flip_y,
-        &mut current_map,
-        &mut current_map_graphics
-    ).unwrap();
-    app_state.overwrite_set(GameState::MapSpawning).unwrap();
+    let mut current_map = current_map.clone();
+    let mut current_map_graphics = current_map_graphics.clone();
+    let flip_y = map_config.flip_y;
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let result = placed_maps
+            .iter()
+            .try_for_each(|(tiled_map, placement)| process_placed_tiled_map(
+                tiled_map,
+                flip_y,
+                placement,
+                &mut current_map,
+                &mut current_map_graphics
+            ));
+        (current_map, current_map_graphics, result)
+    });
+    commands.remove_resource::<CurrentMap>();
+    commands.remove_resource::<CurrentMapGraphics>();
+    commands.insert_resource(MapConstructionTask(task));
+}
+
+// Waits for the map construction task to finish, then either spawns the map or aborts
+fn map_finish_constructing(
+    task: Option<ResMut<MapConstructionTask>>,
+    mut app_state: ResMut<State<GameState>>,
+    mut failed_writer: EventWriter<MapLoadFailedEvent>,
+    mut screen_failed_writer: EventWriter<ScreenLoadFailedEvent>,
+    mut commands: Commands
+) {
+    log::debug!("(SYSTEM) map_finish_constructing");
+
+    // Polls task
+    let mut task = match task {
+        Some(task) => task,
+        None => return
+    };
+    let (current_map, current_map_graphics, result) = match future::block_on(future::poll_once(&mut task.0)) {
+        Some(output) => output,
+        None => return
+    };
+    commands.remove_resource::<MapConstructionTask>();
+
+    // Hands staging resources back
+    match result {
+        Ok(()) => {
+            commands.insert_resource(current_map);
+            commands.insert_resource(current_map_graphics);
+            app_state.overwrite_set(GameState::MapSpawning).unwrap();
+        }
+        Err(error) => abort_map_loading(&current_map, error, &mut app_state, &mut failed_writer, &mut screen_failed_writer, &mut commands)
+    }
 }
 
 fn map_spawn_entities(
     current_map: Res<CurrentMap>,
-    current_map_graphics: ResMut<CurrentMapGraphics>,
-    assets: Res<AssetServer>,
+    mut current_map_graphics: ResMut<CurrentMapGraphics>,
+    replaced_entities: Query<Entity, (With<MapEntity>, Or<(With<MapChunk>, With<Terrain>)>)>,
     mut screen_writer: EventWriter<ScreenLoadedEvent>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut spawned_writer: EventWriter<MapSpawnedEvent>,
+    mut object_writer: EventWriter<MapObjectEvent>,
+    mut gravity: ResMut<Gravity>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
     mut state: ResMut<State<GameState>>,
     mut commands: Commands
 ) {
     log::debug!("(SYSTEM) map_spawn_entities");
 
-    // Quits if graphics haven't finished loading yet
-    let current_map_graphics = current_map_graphics.into_inner();
-    if current_map_graphics.get_load_state(&assets) != LoadState::Loaded {
-        return
+    let owner = MapEntity(current_map.name.clone());
+
+    // When reloading, replaces the chunks and terrain of the spawned map. Everything else stays where it is.
+    if current_map.reloading {
+        for entity in &replaced_entities {
+            commands.entity(entity).despawn_recursive();
+        }
     }
 
-    // Spawns chunks as PBRBundles
-    let image_handles = &current_map_graphics.tileset_handles;
-    for (key, chunk) in &current_map_graphics.chunks {
+    // Hands chunks and terrain over to be streamed in around the camera and physics entities.
+    // The spawned terrain starts out empty.
+    let terrain = &current_map.terrain;
+    commands.spawn()
+        .insert(Terrain::new(terrain.piece_size(), terrain.chunk_size()))
+        .insert(owner.clone());
+    commands.insert_resource(StreamedMap::new(owner.clone(), std::mem::take(current_map_graphics.as_mut()), terrain.clone()));
 
-        // Try to get texture for current chunk
-        let image_handle = match &image_handles[key.tileset_handle_index] {
-            Some(handle) => handle,
-            None => return
-        };
-
-        // Creates mesh for chunk
-        let chunk_size = current_map_graphics.chunk_size;
-        let chunk_pos = Vec3::new(key.x as f32, key.y as f32, key.z as f32) * chunk_size;
-        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, chunk.positions.clone());
-        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, chunk.normals.clone());
-        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, chunk.uvs.clone());
-        mesh.set_indices(Some(Indices::U32(chunk.indices.clone())));
-
-        // Creates material for chunk
-        let material = StandardMaterial {
-            base_color_texture: Some(image_handle.clone()),
-            metallic: 0.0,
-            reflectance: 0.0,
-            perceptual_roughness: 1.0,
-            alpha_mode: AlphaMode::Mask(0.5),
-            ..Default::default()
-        };
-
-        // Turns mesh and material into handles
-        let mesh_handle = meshes.add(mesh);
-        let material_handle = materials.add(material);
-
-        // Creates entity for chunk
-        commands
-            .spawn_bundle(PbrBundle {
-                mesh: mesh_handle,
-                material: material_handle,
-                transform: Transform::from_translation(chunk_pos),
-                ..Default::default()
-            });
+    // Removes staging resources, and remembers the spawned map
+    commands.remove_resource::<CurrentMap>();
+    commands.remove_resource::<CurrentMapGraphics>();
+    commands.insert_resource(LoadedMap::new(&current_map));
+
+    // A reloaded map keeps the lights, camera and objects of the map it replaced
+    if current_map.reloading {
+        state.pop().unwrap();
+        spawned_writer.send(MapSpawnedEvent(current_map.name.clone()));
+        log::info!("Reloaded map '{}'", current_map.name);
+        return;
     }
 
+    // Reads settings from the map's properties
+    let settings = match MapSettings::from_properties(&current_map.properties, &current_map.name) {
+        Ok(settings) => settings,
+        Err(error) => {
+            log::warn!("Using default settings for map '{}': {}", current_map.name, error);
+            MapSettings::default()
+        }
+    };
+
     // Spawns/configures lights
     commands.spawn_bundle(DirectionalLightBundle {
         directional_light: DirectionalLight {
-            color: Color::WHITE,
-            illuminance: 27500.0,
+            color: settings.light_color,
+            illuminance: settings.light_illuminance,
             ..Default::default()
         },
-        transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)).looking_towards(Vec3::new(0.0, -1.0, -1.0), Vec3::Y),
+        transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)).looking_towards(settings.light_direction(), Vec3::Y),
         ..Default::default()
-    });
-
-    // Spawns terrain
-    commands.spawn().insert(current_map.terrain.clone());
+    }).insert(owner.clone());
 
     // Spawns camera using an in-game CameraBundle
     commands
@@ -265,25 +634,118 @@ fn map_spawn_entities(
             Velocity(Vec3::ZERO),
             Friction { xz: 0.8, y: 0.8 },
             CameraTargetSettings { distance: 512.0 }
-        ));
+        ).with_clear_color(settings.clear_color))
+        .insert(owner);
+
+    // Maps without a gravity of their own use the default
+    *gravity = settings.gravity
+        .map(|gravity| Gravity { gravity })
+        .unwrap_or_default();
+
+    // Starts background music
+    if let Some(music) = &settings.music {
+        let sink = audio.play_with_settings(asset_server.load(music.as_str()), PlaybackSettings::LOOP);
+        commands.insert_resource(MapMusic(audio_sinks.get_handle(sink)));
+    }
+
+    // Announces objects placed in the map so that game code can spawn them
+    for object in &current_map.objects {
+        object_writer.send(MapObjectEvent(object.clone()));
+    }
 
-    // Removes staging resources
-    commands.remove_resource::<CurrentMap>();
-    commands.remove_resource::<CurrentMapGraphics>();
-    
     // Finishes map loading
     state.pop().unwrap();
+    spawned_writer.send(MapSpawnedEvent(current_map.name.clone()));
     screen_writer.send(ScreenLoadedEvent);
     log::debug!("Done spawning map graphics entities...");
 }
 
+// Discards the map being loaded, restores the state stack and reports the error
+fn abort_map_loading(
+    current_map: &CurrentMap,
+    error: MapLoadError,
+    state: &mut State<GameState>,
+    failed_writer: &mut EventWriter<MapLoadFailedEvent>,
+    screen_failed_writer: &mut EventWriter<ScreenLoadFailedEvent>,
+    commands: &mut Commands
+) {
+    log::error!("Failed to load map '{}': {}", current_map.name, error);
+
+    // Removes staging resources
+    commands.remove_resource::<CurrentMap>();
+    commands.remove_resource::<CurrentMapGraphics>();
+
+    // Goes back to the state that was active before loading began
+    state.pop().unwrap();
+    failed_writer.send(MapLoadFailedEvent {
+        name: current_map.name.clone(),
+        error
+    });
+
+    // A map that fails to reload leaves the spawned map as it was, so the screen itself is fine
+    if !current_map.reloading {
+        screen_failed_writer.send(ScreenLoadFailedEvent);
+    }
+}
+
 /// Map configuration resource
 #[derive(Debug, PartialEq)]
 pub struct MapConfig {
     pub chunk_size: Vec3,
-    pub flip_y: bool
+    /// Treats tileset images as if they were stored upside-down.
+    /// Can be overridden with a "flip_y" bool property on the map or on individual tilesets.
+    pub flip_y: bool,
+    /// Chunk meshes within this distance of the [`crate::camera::MainCamera`] are spawned
+    pub chunk_load_radius: f32,
+    /// Chunk meshes further than this from the main camera are despawned.
+    /// Larger than the load radius, so that chunks at the edge don't pop in and out.
+    pub chunk_unload_radius: f32,
+    /// Terrain chunks within this distance of a physics entity are added to the spawned [`Terrain`]
+    pub terrain_load_radius: f32,
+    /// Terrain chunks further than this from every physics entity are removed from the spawned [`Terrain`]
+    pub terrain_unload_radius: f32
 }
 
-// Fired when map has fully spawned
+impl Default for MapConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: Vec3::new(
+                (16*16) as f32,
+                (16*16) as f32,
+                (16*16) as f32
+            ),
+            flip_y: false,
+            chunk_load_radius: 2048.0,
+            chunk_unload_radius: 2560.0,
+            terrain_load_radius: 512.0,
+            terrain_unload_radius: 768.0
+        }
+    }
+}
+
+/// Fired with the name of a map once it has fully spawned, or finished reloading.
+/// Objects are announced with [`MapObjectEvent`]s in the same frame.
+#[derive(Debug, Clone)]
+pub struct MapSpawnedEvent(pub String);
+
+/// Fired with the name of a map once its entities have been despawned, when the screen changes.
 #[derive(Debug, Clone)]
-pub struct MapSpawnedEvent(pub String);
\ No newline at end of file
+pub struct MapUnloadedEvent(pub String);
+
+/// Component of every entity owned by a map, holding the name of that map.
+/// Entities with this component are despawned when the map unloads, regardless of [`crate::screen::Keep`].
+/// Game code may insert it on the entities it spawns for [`MapObjectEvent`]s so that they go away with the map.
+#[derive(Component, Debug, Clone, Eq, PartialEq)]
+pub struct MapEntity(pub String);
+
+/// Resource holding the task that constructs the map off the main thread.
+/// Outputs the staging resources and the result of construction.
+pub struct MapConstructionTask(Task<(CurrentMap, CurrentMapGraphics, Result<(), MapLoadError>)>);
+
+/// Resource holding the background music of the spawned map, if it has any.
+/// The music stops when the screen changes.
+pub struct MapMusic(pub Handle<AudioSink>);
+
+/// Marker component for the entities that hold a map's chunk meshes
+#[derive(Component, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MapChunk;
\ No newline at end of file
@@ -0,0 +1,26 @@
+use bevy::prelude::*;
+use tiled::Properties;
+
+/// Object read from a Tiled object layer (spawn points, NPCs, chests, signs, etc).
+/// Position is in world space, placed on top of the terrain it was drawn over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapObject {
+    /// Id of the object in the map file
+    pub id: u32,
+    /// Name of the object
+    pub name: String,
+    /// User-defined type of the object
+    pub typ: String,
+    /// World position of the object
+    pub position: Vec3,
+    /// Width and height of the object in pixels. Zero for points.
+    pub size: Vec2,
+    /// Name of the object layer the object came from, prefixed by its group layer name if it had one
+    pub layer_name: String,
+    /// Custom properties of the object
+    pub properties: Properties
+}
+
+/// Fired once for every [`MapObject`] in a map after the map has spawned.
+#[derive(Debug, Clone)]
+pub struct MapObjectEvent(pub MapObject);
@@ -0,0 +1,322 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use tiled::Map;
+
+use crate::map::{
+    CurrentMap, CurrentMapGraphics, LoadedMap, MapConfig, MapEntity, MapLoadError, MapPlacement, StreamedMap,
+    TileType, VidyaMap, VidyaWorld, placed_maps, process_tiled_map_columns
+};
+use crate::physics::{ChunkCoords, Coords, Terrain, TerrainPiece};
+
+/// Send to change the terrain of the spawned map, such as to break a wall or raise a bridge.
+/// Edits are applied in the order they were sent, and each one that changes something fires a [`TerrainChangedEvent`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditTerrainEvent {
+    /// Changes the type of a tile, as if the map had a different meta tile there.
+    /// Regenerates the terrain and graphics of every column of tiles that shares a graphics chunk with the tile.
+    /// Only maps loaded from TMX or world files can be edited this way, since baked maps have no layers to regenerate from.
+    SetTileType {
+        tile: IVec2,
+        tile_type: TileType
+    },
+    /// Sets terrain pieces directly, leaving graphics as they are.
+    /// Pieces set this way are overwritten if their column is later regenerated by [`EditTerrainEvent::SetTileType`].
+    SetPieces(Vec<(Coords, TerrainPiece)>)
+}
+
+/// Fired after an edit changes the terrain of the spawned map.
+/// Min and max are the inclusive bounds of the terrain pieces that changed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TerrainChangedEvent {
+    pub min: Coords,
+    pub max: Coords
+}
+
+/// Applies [`EditTerrainEvent`]s to the spawned map
+pub(crate) fn apply_terrain_edits(
+    mut edit_events: EventReader<EditTerrainEvent>,
+    mut changed_writer: EventWriter<TerrainChangedEvent>,
+    loaded_map: Option<ResMut<LoadedMap>>,
+    streamed_map: Option<ResMut<StreamedMap>>,
+    mut terrain_query: Query<&mut Terrain, With<MapEntity>>,
+    vidya_maps: Res<Assets<VidyaMap>>,
+    vidya_worlds: Res<Assets<VidyaWorld>>,
+    map_config: Res<MapConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands
+) {
+    log::debug!("(SYSTEM) apply_terrain_edits");
+
+    // Edits sent while no map is spawned are dropped
+    let (mut loaded_map, mut streamed_map) = match (loaded_map, streamed_map) {
+        (Some(loaded_map), Some(streamed_map)) => (loaded_map, streamed_map),
+        _ => {
+            edit_events.clear();
+            return;
+        }
+    };
+    let mut spawned_terrain = terrain_query.iter_mut().next();
+
+    for event in edit_events.iter() {
+
+        // Works out which pieces to change
+        let pieces = match event {
+            EditTerrainEvent::SetPieces(pieces) => pieces.clone(),
+            EditTerrainEvent::SetTileType { tile, tile_type } => {
+                if !loaded_map.bounds.contains(tile.x, tile.y) {
+                    log::warn!("Can't set type of tile {} in map '{}', as it's outside of the map", tile, loaded_map.name);
+                    continue;
+                }
+                let placed_maps = match placed_maps(&loaded_map.map_handle, loaded_map.world_handle.as_ref(), &vidya_maps, &vidya_worlds) {
+                    Some(placed_maps) => placed_maps,
+                    None => {
+                        log::warn!("Can't set type of tile {} in map '{}', as it has no layers to regenerate from", tile, loaded_map.name);
+                        continue;
+                    }
+                };
+                let result = set_tile_type(
+                    *tile,
+                    *tile_type,
+                    &placed_maps,
+                    map_config.flip_y,
+                    &mut loaded_map,
+                    &mut streamed_map,
+                    &mut meshes,
+                    &mut materials,
+                    &mut commands
+                );
+                match result {
+                    Ok(pieces) => pieces,
+                    Err(error) => {
+                        log::error!("Failed to set type of tile {} in map '{}': {}", tile, loaded_map.name, error);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        // Writes them to the terrain of the whole map, and to the spawned terrain if their chunks are streamed in
+        let spawned_terrain = spawned_terrain.as_mut().map(|terrain| terrain.as_mut());
+        if let Some((min, max)) = set_pieces(&pieces, &mut streamed_map.terrain, spawned_terrain) {
+            changed_writer.send(TerrainChangedEvent { min, max });
+        }
+    }
+}
+
+// Changes the type of a tile and regenerates the columns of tiles sharing a graphics chunk with it.
+// Replaces the graphics chunks of those columns, and returns the terrain pieces of those columns.
+fn set_tile_type(
+    tile: IVec2,
+    tile_type: TileType,
+    placed_maps: &[(Arc<Map>, MapPlacement)],
+    flip_y: bool,
+    loaded_map: &mut LoadedMap,
+    streamed_map: &mut StreamedMap,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    commands: &mut Commands
+) -> Result<Vec<(Coords, TerrainPiece)>, MapLoadError> {
+
+    // Finds the columns of tiles that fall within the same slab of graphics chunks as the tile
+    let tile_width = loaded_map.tile_size.x;
+    let chunk_width = streamed_map.chunk_size.x;
+    let chunk_x = (tile.x as f32 * tile_width / chunk_width).floor() as i32;
+    let first_column = |chunk_x: i32| (chunk_x as f32 * chunk_width / tile_width).ceil() as i32;
+    let columns = first_column(chunk_x)..first_column(chunk_x + 1);
+
+    // Traverses those columns again, with the new tile type
+    let mut edited_tiles = loaded_map.edited_tiles.clone();
+    edited_tiles.insert(tile, tile_type);
+    let (piece_size, terrain_chunk_size) = (streamed_map.terrain.piece_size(), streamed_map.terrain.chunk_size());
+    let mut current_map = CurrentMap {
+        terrain: Terrain::new(piece_size, terrain_chunk_size),
+        meta_overrides: edited_tiles,
+        ..CurrentMap::new(loaded_map.name.clone(), loaded_map.map_handle.clone())
+    };
+    let mut current_map_graphics = CurrentMapGraphics {
+        tileset_atlases: streamed_map.tileset_atlases.clone(),
+        ..CurrentMapGraphics::new(streamed_map.chunk_size)
+    };
+    for (tiled_map, placement) in placed_maps {
+        process_tiled_map_columns(tiled_map, flip_y, placement, columns.clone(), &mut current_map, &mut current_map_graphics)?;
+    }
+
+    // Keeps the results
+    loaded_map.edited_tiles = current_map.meta_overrides;
+    loaded_map.meta_tiles.retain(|tile, _| !columns.contains(&tile.x));
+    loaded_map.meta_tiles.extend(current_map.meta_tiles);
+    streamed_map.replace_chunks(|key| key.x == chunk_x, current_map_graphics.chunks, meshes, materials, commands);
+
+    // Pieces of the regenerated columns, including the ones that are now empty
+    let piece_column = |column: i32| (column as f32 * tile_width / piece_size.x).floor() as i32;
+    let piece_columns = piece_column(columns.start)..piece_column(columns.end);
+    Ok(column_pieces(&streamed_map.terrain, &current_map.terrain, piece_columns))
+}
+
+// Pieces of the new terrain within a range of x coordinates, covering every chunk that either terrain has there
+fn column_pieces(old_terrain: &Terrain, new_terrain: &Terrain, columns: Range<i32>) -> Vec<(Coords, TerrainPiece)> {
+    let chunk_size = new_terrain.chunk_size().as_ivec3();
+    let chunk_coords: HashSet<ChunkCoords> = old_terrain
+        .chunks()
+        .chain(new_terrain.chunks())
+        .map(|(coords, _)| coords)
+        .filter(|coords| coords.x * chunk_size.x < columns.end && (coords.x + 1) * chunk_size.x > columns.start)
+        .collect();
+    let mut pieces = Vec::new();
+    for chunk in chunk_coords {
+        let min = IVec3::new(chunk.x, chunk.y, chunk.z) * chunk_size;
+        let max = min + chunk_size;
+        for x in min.x.max(columns.start)..max.x.min(columns.end) {
+            for y in min.y..max.y {
+                for z in min.z..max.z {
+                    let coords = Coords::new(x, y, z);
+                    let piece = new_terrain.get(coords).copied().unwrap_or(TerrainPiece::Empty);
+                    pieces.push((coords, piece));
+                }
+            }
+        }
+    }
+    pieces
+}
+
+// Sets pieces of the terrain of the whole map, and of the spawned terrain where its chunks exist.
+// Returns the inclusive bounds of the pieces that changed, if any did.
+fn set_pieces(
+    pieces: &[(Coords, TerrainPiece)],
+    terrain: &mut Terrain,
+    mut spawned_terrain: Option<&mut Terrain>
+) -> Option<(Coords, Coords)> {
+    let mut bounds: Option<(Coords, Coords)> = None;
+    for (coords, piece) in pieces.iter().copied() {
+        if terrain.get(coords).copied().unwrap_or(TerrainPiece::Empty) == piece {
+            continue;
+        }
+        *terrain.get_or_create_mut(coords) = piece;
+        if let Some(spawned_terrain) = spawned_terrain.as_mut() {
+            if spawned_terrain.get(coords).is_some() {
+                *spawned_terrain.get_or_create_mut(coords) = piece;
+            }
+        }
+        bounds = Some(match bounds {
+            Some((min, max)) => (
+                Coords::new(min.x.min(coords.x), min.y.min(coords.y), min.z.min(coords.z)),
+                Coords::new(max.x.max(coords.x), max.y.max(coords.y), max.z.max(coords.z))
+            ),
+            None => (coords, coords)
+        });
+    }
+    bounds
+}
+
+
+#[test]
+fn test_set_pieces() {
+    let mut terrain = Terrain::new(Vec3::new(16.0, 16.0, 16.0), UVec3::new(4, 4, 4));
+    *terrain.get_or_create_mut(Coords::new(1, 0, 0)) = TerrainPiece::Cuboid;
+    *terrain.get_or_create_mut(Coords::new(5, 0, 0)) = TerrainPiece::Cuboid;
+
+    // Only the first chunk is streamed in
+    let mut spawned_terrain = Terrain::new(Vec3::new(16.0, 16.0, 16.0), UVec3::new(4, 4, 4));
+    *spawned_terrain.get_or_create_mut(Coords::new(1, 0, 0)) = TerrainPiece::Cuboid;
+
+    let pieces = vec![
+        (Coords::new(1, 0, 0), TerrainPiece::Cuboid),
+        (Coords::new(2, 1, 0), TerrainPiece::Slope),
+        (Coords::new(5, 0, 0), TerrainPiece::Empty),
+        (Coords::new(6, 0, -1), TerrainPiece::Empty)
+    ];
+    let bounds = set_pieces(&pieces, &mut terrain, Some(&mut spawned_terrain));
+    assert_eq!(Some((Coords::new(2, 0, 0), Coords::new(5, 1, 0))), bounds);
+    assert_eq!(Some(&TerrainPiece::Slope), terrain.get(Coords::new(2, 1, 0)));
+    assert_eq!(Some(&TerrainPiece::Empty), terrain.get(Coords::new(5, 0, 0)));
+    assert_eq!(None, terrain.get(Coords::new(6, 0, -1)));
+    assert_eq!(Some(&TerrainPiece::Slope), spawned_terrain.get(Coords::new(2, 1, 0)));
+    assert_eq!(None, spawned_terrain.get(Coords::new(5, 0, 0)));
+
+    // Nothing to change
+    assert_eq!(None, set_pieces(&pieces, &mut terrain, None));
+}
+
+#[test]
+fn test_set_tile_type() {
+    use bevy::asset::AssetPlugin;
+    use bevy::ecs::system::CommandQueue;
+    use bevy::utils::HashMap;
+    use crate::map::{Chunk, ChunkKey, MapBuilder, MapConfig};
+
+    // Floors spanning two graphics chunks, with a wall in the second one once edited
+    let floors = |wall: Option<IVec2>| {
+        let mut builder = MapBuilder::new("edit.tmx", UVec2::new(20, 3), UVec2::new(16, 16));
+        let tileset = builder.add_tileset("cliffs", "tilesets/cliffs.png", UVec2::new(64, 64));
+        let ground = builder.add_group("ground", 0, false);
+        for y in 0..3 {
+            for x in 0..20 {
+                builder.set_tile(ground, 0, IVec2::new(x, y), tileset, 0);
+            }
+        }
+        if let Some(wall) = wall {
+            builder.set_tile_type(ground, wall, TileType::Wall);
+        }
+        builder
+    };
+    let map_config = MapConfig::default();
+    let wall = IVec2::new(17, 1);
+    let builder = floors(None);
+    let (current_map, current_map_graphics) = builder.build(&map_config).unwrap();
+    let (walled_map, walled_graphics) = floors(Some(wall)).build(&map_config).unwrap();
+    let tiled_map = builder.build_tiled_map().unwrap();
+    let placement = MapPlacement::new(&tiled_map);
+    let placed_maps = vec![(Arc::new(tiled_map), placement)];
+
+    let mut loaded_map = LoadedMap::new(&current_map);
+    let mut streamed_map = StreamedMap::new(MapEntity("edit.tmx".to_owned()), current_map_graphics.clone(), current_map.terrain.clone());
+    let mut app = App::new();
+    app
+        .add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin)
+        .add_asset::<Mesh>()
+        .add_asset::<StandardMaterial>();
+    let mut meshes = app.world.remove_resource::<Assets<Mesh>>().unwrap();
+    let mut materials = app.world.remove_resource::<Assets<StandardMaterial>>().unwrap();
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, &app.world);
+    let pieces = set_tile_type(
+        wall,
+        TileType::Wall,
+        &placed_maps,
+        map_config.flip_y,
+        &mut loaded_map,
+        &mut streamed_map,
+        &mut meshes,
+        &mut materials,
+        &mut commands
+    ).unwrap();
+
+    // Pieces cover every column of the second chunk, including empty ones, and match a map built with the wall
+    assert!(pieces.iter().all(|(coords, _)| (16..32).contains(&coords.x)));
+    assert!(pieces.iter().any(|(coords, piece)| coords.x == 31 && *piece == TerrainPiece::Empty));
+    assert!(pieces.contains(&(Coords::new(17, 0, -1), TerrainPiece::Cuboid)));
+    for (coords, piece) in &pieces {
+        assert_eq!(walled_map.terrain.get(*coords).copied().unwrap_or(TerrainPiece::Empty), *piece);
+    }
+    assert_eq!(Some(&TileType::Wall), loaded_map.edited_tiles.get(&wall));
+    assert_eq!(Some(&TileType::Wall), loaded_map.meta_tiles.get(&wall));
+
+    // Graphics of the second chunk are replaced, and the first chunk is left alone
+    let chunks_at = |chunks: &HashMap<ChunkKey, Chunk>, chunk_x: i32| {
+        let mut chunks: Vec<_> = chunks
+            .iter()
+            .filter(|(key, _)| key.x == chunk_x)
+            .map(|(key, chunk)| ((key.y, key.z, key.tileset_handle_index), chunk.clone()))
+            .collect();
+        chunks.sort_by_key(|(key, _)| *key);
+        chunks
+    };
+    assert!(chunks_at(&streamed_map.chunks, 1) == chunks_at(&walled_graphics.chunks, 1));
+    assert!(chunks_at(&streamed_map.chunks, 0) == chunks_at(&current_map_graphics.chunks, 0));
+    assert!(chunks_at(&streamed_map.chunks, 1) != chunks_at(&current_map_graphics.chunks, 1));
+}
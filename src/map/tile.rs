@@ -1,4 +1,5 @@
 use bevy::prelude::*;
+use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 /// Tile id local to a tileset
 pub type LocalId = u32;
@@ -18,12 +19,77 @@ pub struct TileMeshData {
     pub uv1: Vec2,
     pub uv2: Vec2,
     pub uv3: Vec2,
-    pub uv4: Vec2
+    pub uv4: Vec2,
+    /// Tile is mirrored horizontally
+    pub flip_h: bool,
+    /// Tile is mirrored vertically
+    pub flip_v: bool,
+    /// Tile is mirrored across its top-left to bottom-right diagonal. Combined with the other flips, this rotates the tile.
+    pub flip_d: bool
+}
+
+impl TileMeshData {
+
+    /// UVs of the bottom-left, bottom-right, top-right and top-left corners of the tile, with flips applied.
+    /// Like in Tiled, the diagonal flip is applied first, followed by the horizontal and vertical flips.
+    pub fn uvs(&self) -> [Vec2; 4] {
+        const FLIP_H: [usize; 4] = [1, 0, 3, 2];
+        const FLIP_V: [usize; 4] = [3, 2, 1, 0];
+        const FLIP_D: [usize; 4] = [2, 1, 0, 3];
+        let mut uvs = [self.uv1, self.uv2, self.uv3, self.uv4];
+        let flips = [(self.flip_d, FLIP_D), (self.flip_h, FLIP_H), (self.flip_v, FLIP_V)];
+        for (flipped, permutation) in flips {
+            if flipped {
+                let prev = uvs;
+                for (corner, uv) in uvs.iter_mut().enumerate() {
+                    *uv = prev[permutation[corner]];
+                }
+            }
+        }
+        uvs
+    }
+}
+
+/// Rectangle of tile coordinates occupied by a map, in the map's own (top-down) tile space.
+/// Min is inclusive and max is exclusive. Infinite maps can have negative coordinates.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct TileBounds {
+    pub min: IVec2,
+    pub max: IVec2
+}
+
+impl TileBounds {
+    pub fn new(min: IVec2, max: IVec2) -> Self {
+        Self { min, max }
+    }
+
+    /// Width and height of the bounds in tiles
+    pub fn size(&self) -> IVec2 {
+        (self.max - self.min).max(IVec2::ZERO)
+    }
+
+    /// True if the bounds contain no tiles
+    pub fn is_empty(&self) -> bool {
+        self.max.x <= self.min.x || self.max.y <= self.min.y
+    }
+
+    /// True if the tile at x, y is within the bounds
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.min.x && x < self.max.x && y >= self.min.y && y < self.max.y
+    }
+
+    /// Smallest bounds containing both bounds
+    pub fn union(&self, other: TileBounds) -> TileBounds {
+        if self.is_empty() { return other; }
+        if other.is_empty() { return *self; }
+        TileBounds::new(self.min.min(other.min), self.max.max(other.max))
+    }
 }
 
 /// Type of meta tile this is.
 /// Maps directly to what is is in a map file.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
 pub enum TileType {
     Floor,
     Wall,
@@ -38,7 +104,9 @@ pub enum TileType {
     SlopeStartE,
     SlopeEndE,
     SlopeStartW,
-    SlopeEndW
+    SlopeEndW,
+    Stairs,
+    Step
 }
 
 impl TileType {
@@ -58,10 +126,39 @@ impl TileType {
             "slope-end-e" => Some(Self::SlopeEndE),
             "slope-start-w" => Some(Self::SlopeStartW),
             "slope-end-w" => Some(Self::SlopeEndW),
+            "stairs" => Some(Self::Stairs),
+            "step" => Some(Self::Step),
             _ => None
         }
     }
 
+    /// Name of the type, as written in the "type" property of meta tiles
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Floor => "floor",
+            Self::Wall => "wall",
+            Self::WallStartSE => "wall-start-se",
+            Self::WallEndSE => "wall-end-se",
+            Self::WallStartSW => "wall-start-sw",
+            Self::WallEndSW => "wall-end-sw",
+            Self::LipN => "lip-n",
+            Self::LipNE => "lip-ne",
+            Self::LipNW => "lip-nw",
+            Self::Slope => "slope",
+            Self::SlopeStartE => "slope-start-e",
+            Self::SlopeEndE => "slope-end-e",
+            Self::SlopeStartW => "slope-start-w",
+            Self::SlopeEndW => "slope-end-w",
+            Self::Stairs => "stairs",
+            Self::Step => "step"
+        }
+    }
+
+    /// Every tile type, in the order of their values
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..=u8::MAX).map_while(|value| Self::try_from(value).ok())
+    }
+
     pub fn is_lip(self) -> bool {
         self == Self::LipN ||
         self == Self::LipNE ||
@@ -81,10 +178,52 @@ pub enum TileShape {
     WallSW,
     WallEndSW,
     SlopeS,
+    LipN,
+    LipNE,
+    LipNW,
     SlopeStartE,
     SlopeE,
     SlopeEndE,
     SlopeStartW,
     SlopeW,
     SlopeEndW,
+    /// Single stair step: a riser half a tile tall, followed by a tread half a tile deep
+    Step
+}
+
+
+#[test]
+fn test_flipped_uvs() {
+    let (bl, br, tr, tl) = (Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0), Vec2::new(1.0, 0.0), Vec2::new(0.0, 0.0));
+    let data = TileMeshData {
+        size: Vec2::new(16.0, 16.0),
+        uv1: bl,
+        uv2: br,
+        uv3: tr,
+        uv4: tl,
+        flip_h: false,
+        flip_v: false,
+        flip_d: false
+    };
+    assert_eq!([bl, br, tr, tl], data.uvs());
+    assert_eq!([br, bl, tl, tr], TileMeshData { flip_h: true, ..data }.uvs());
+    assert_eq!([tl, tr, br, bl], TileMeshData { flip_v: true, ..data }.uvs());
+    assert_eq!([tr, br, bl, tl], TileMeshData { flip_d: true, ..data }.uvs());
+    assert_eq!([tr, tl, bl, br], TileMeshData { flip_h: true, flip_v: true, ..data }.uvs());
+
+    // Rotated 90 degrees clockwise: The bottom-left of the original image ends up in the top-left
+    assert_eq!([br, tr, tl, bl], TileMeshData { flip_d: true, flip_h: true, ..data }.uvs());
+}
+
+#[test]
+fn test_tile_bounds_union() {
+    let empty = TileBounds::default();
+    let a = TileBounds::new(IVec2::new(-16, 0), IVec2::new(0, 16));
+    let b = TileBounds::new(IVec2::new(0, -32), IVec2::new(16, -16));
+    assert_eq!(a, empty.union(a));
+    assert_eq!(a, a.union(empty));
+    assert_eq!(TileBounds::new(IVec2::new(-16, -32), IVec2::new(16, 16)), a.union(b));
+    assert_eq!(IVec2::new(32, 48), a.union(b).size());
+    assert!(a.contains(-16, 15));
+    assert!(!a.contains(0, 0));
 }
@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+
+use crate::game::GameConfig;
+use crate::map::TileMeshData;
+
+/// Single frame of an animated tile, as read from a tileset.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TileFrame {
+    /// Mesh data of the frame. Only the UVs differ between frames.
+    pub mesh_data: TileMeshData,
+    /// Duration of the frame in milliseconds
+    pub duration: u32
+}
+
+/// Animation of a single tile within a chunk's mesh.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TileAnimation {
+    /// Index of the first vertex of the tile in the chunk's mesh
+    pub vertex_start: usize,
+    /// UVs of the tile's vertices for every frame
+    pub frame_uvs: Vec<Vec<[f32; 2]>>,
+    /// Duration of every frame in milliseconds
+    pub frame_durations: Vec<u32>
+}
+
+impl TileAnimation {
+
+    /// Index of the frame that should be shown after the specified amount of time has elapsed
+    pub fn frame_at(&self, elapsed: Duration) -> usize {
+        let total: u32 = self.frame_durations.iter().sum();
+        if total == 0 {
+            return 0;
+        }
+        let mut millis = (elapsed.as_millis() % total as u128) as u32;
+        for (index, duration) in self.frame_durations.iter().enumerate() {
+            if millis < *duration {
+                return index;
+            }
+            millis -= duration;
+        }
+        0
+    }
+}
+
+/// Component of a map chunk entity that has animated tiles in its mesh.
+#[derive(Component, Debug, Clone)]
+pub struct AnimatedChunk {
+    pub mesh: Handle<Mesh>,
+    pub animations: Vec<TileAnimation>,
+    /// Frame currently written to the mesh for each animation
+    pub frame_indices: Vec<usize>
+}
+
+impl AnimatedChunk {
+    pub fn new(mesh: Handle<Mesh>, animations: Vec<TileAnimation>) -> Self {
+        let frame_indices = vec![usize::MAX; animations.len()];
+        Self { mesh, animations, frame_indices }
+    }
+}
+
+/// Resource that keeps track of how much game time has elapsed for tile animations.
+/// Advances once per tick, so all animated tiles stay in sync with each other and the game.
+#[derive(Debug, Default, Clone)]
+pub struct TileAnimationClock {
+    pub elapsed: Duration
+}
+
+/// Advances the tile animation clock, and writes the UVs of animated tiles whose frame changed
+pub(crate) fn animate_tiles(
+    config: Res<GameConfig>,
+    mut clock: ResMut<TileAnimationClock>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut chunk_query: Query<&mut AnimatedChunk>
+) {
+    clock.elapsed += Duration::from_secs_f64(config.timestep_secs);
+    for mut chunk in &mut chunk_query {
+        let chunk = chunk.as_mut();
+
+        // Finds animations whose frame changed. Quits early if there are none to avoid touching the mesh.
+        let changed: Vec<(usize, usize)> = chunk.animations
+            .iter()
+            .enumerate()
+            .map(|(index, animation)| (index, animation.frame_at(clock.elapsed)))
+            .filter(|(index, frame)| chunk.frame_indices[*index] != *frame)
+            .collect();
+        if changed.is_empty() {
+            continue;
+        }
+
+        // Writes UVs of new frames
+        let mesh = match meshes.get_mut(&chunk.mesh) {
+            Some(mesh) => mesh,
+            None => continue
+        };
+        let uvs = match mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0) {
+            Some(VertexAttributeValues::Float32x2(uvs)) => uvs,
+            _ => continue
+        };
+        for (index, frame) in changed {
+            let animation = &chunk.animations[index];
+            let frame_uvs = &animation.frame_uvs[frame];
+            let start = animation.vertex_start;
+            uvs[start..start + frame_uvs.len()].copy_from_slice(frame_uvs);
+            chunk.frame_indices[index] = frame;
+        }
+    }
+}
+
+
+#[test]
+fn test_frame_at() {
+    let animation = TileAnimation {
+        vertex_start: 0,
+        frame_uvs: vec![Vec::new(); 3],
+        frame_durations: vec![100, 200, 100]
+    };
+    assert_eq!(0, animation.frame_at(Duration::from_millis(0)));
+    assert_eq!(0, animation.frame_at(Duration::from_millis(99)));
+    assert_eq!(1, animation.frame_at(Duration::from_millis(100)));
+    assert_eq!(1, animation.frame_at(Duration::from_millis(299)));
+    assert_eq!(2, animation.frame_at(Duration::from_millis(300)));
+    assert_eq!(0, animation.frame_at(Duration::from_millis(400)));
+    assert_eq!(1, animation.frame_at(Duration::from_millis(550)));
+}
@@ -8,18 +8,22 @@ use crate::physics::{Coords};
 pub(crate) struct Climber {
     climb_status: ClimbStatus,
     prev_status: ClimbStatus,
+    tile_type: TileType,
     position: Vec3,
     next_position: Vec3,
-    offset: Vec2,
+    offset: Vec3,
     tile_size: Vec3
 }
 
 impl Climber {
-    pub fn new(offset: Vec2, tile_size: Vec3) -> Self {
-        let position = Vec3::new(offset.x, offset.y, 0.0);
+    /// Creates a climber that starts at the offset specified.
+    /// The offset is the position of the bottom tile of the strip being climbed.
+    pub fn new(offset: Vec3, tile_size: Vec3) -> Self {
+        let position = offset;
         Self {
             climb_status: ClimbStatus::NotClimbing,
             prev_status: ClimbStatus::NotClimbing,
+            tile_type: TileType::Floor,
             position,
             next_position: position,
             offset,
@@ -53,16 +57,13 @@ impl Climber {
     /// Compares current climb status and the next tile encountered, and "climbs" appropriately.
     pub fn climb(
         &mut self,
-        tile_type: TileType,
-        tile_x: i32,
-        tile_y: i32,
-        group_layer_name: &str
+        tile_type: TileType
     ) -> Result<(), ClimbingError> {
 
         // Goes to next position and determines new climb status
         let position = self.next_position;
         let prev_status = self.climb_status;
-        self.climb_status = ClimbStatus::next(self.climb_status, tile_type, tile_x, tile_y, group_layer_name)?;
+        self.climb_status = ClimbStatus::next(self.climb_status, tile_type)?;
 
         // Advances position based on our new status
         if self.climb_status == ClimbStatus::NotClimbing {
@@ -71,10 +72,24 @@ impl Climber {
         else if self.climb_status.is_climbing_wall() {
             self.next_position.y += self.tile_size.y;
         }
-        else if self.climb_status == ClimbStatus::ClimbingSlopeFirst || self.climb_status == ClimbStatus::ClimbingSlopeSecond {
+        else if
+            self.climb_status == ClimbStatus::ClimbingSlopeFirst ||
+            self.climb_status == ClimbStatus::ClimbingSlopeSecond ||
+            self.climb_status.is_climbing_step()
+        {
             self.next_position.y += self.tile_size.y * 0.5;
             self.next_position.z -= self.tile_size.z * 0.5;
         }
+        else if self.climb_status == ClimbStatus::ClimbingSlopeStartE {
+            self.next_position.y += self.tile_size.y;
+        }
+        else if
+            self.climb_status == ClimbStatus::ClimbingSlopeE ||
+            self.climb_status == ClimbStatus::ClimbingSlopeStartW ||
+            self.climb_status == ClimbStatus::ClimbingSlopeW
+        {
+            self.next_position.z -= self.tile_size.y;
+        }
         else if self.climb_status == ClimbStatus::FinishedClimbing {
             let ydiff = self.next_position.y - self.offset.y;
             self.next_position.y = self.offset.y;
@@ -84,11 +99,21 @@ impl Climber {
         // Finishes
         self.position = position;
         self.prev_status = prev_status;
+        self.tile_type = tile_type;
         Ok(())
     }
 
     pub fn climb_status(&self) -> ClimbStatus { self.climb_status }
 
+    /// Climb status of the tile before the current one
+    pub fn prev_status(&self) -> ClimbStatus { self.prev_status }
+
+    /// True if the climber is half a tile above the tile grid, which happens after climbing a single step
+    pub fn is_half_height(&self) -> bool {
+        let fract = (self.position.y / self.tile_size.y).fract().abs();
+        fract > 0.25 && fract < 0.75
+    }
+
     /// Determines the shape of the current tile being scanned, if any.
     /// Used for graphics.
     pub fn tile_shape(&self) -> Result<TileShape, ClimbingError> {
@@ -102,8 +127,11 @@ impl Climber {
         match self.climb_status {
             ClimbStatus::NotClimbing => match self.prev_status {
                 ClimbStatus::ClimbingWallS | ClimbStatus::NotClimbing | ClimbStatus::FinishedClimbing | ClimbStatus::ClimbingSlopeSecond => Ok(TileShape::Floor),
+                ClimbStatus::ClimbingStairsSecond | ClimbStatus::ClimbingStep => Ok(TileShape::Floor),
                 ClimbStatus::ClimbingWallSE => Ok(TileShape::WallEndSE),
                 ClimbStatus::ClimbingWallSW => Ok(TileShape::WallEndSW),
+                ClimbStatus::ClimbingSlopeStartE | ClimbStatus::ClimbingSlopeE => Ok(TileShape::SlopeEndE),
+                ClimbStatus::ClimbingSlopeStartW | ClimbStatus::ClimbingSlopeW => Ok(TileShape::SlopeEndW),
                 _ => Err(make_error())
             }
             ClimbStatus::ClimbingWallS => Ok(TileShape::Wall),
@@ -117,17 +145,25 @@ impl Climber {
                 ClimbStatus::ClimbingWallSW => Ok(TileShape::WallSW),
                 _ => Err(make_error())
             },
-            ClimbStatus::FinishedClimbing => match self.prev_status {
-                ClimbStatus::FinishedClimbing => Err(make_error()),
-                _ => Ok(TileShape::Floor)
+            ClimbStatus::FinishedClimbing => match (self.prev_status, self.tile_type) {
+                (ClimbStatus::FinishedClimbing, _) => Err(make_error()),
+                (_, TileType::LipNE) => Ok(TileShape::LipNE),
+                (_, TileType::LipNW) => Ok(TileShape::LipNW),
+                _ => Ok(TileShape::LipN)
             },
             ClimbStatus::ClimbingSlopeFirst => Ok(TileShape::SlopeS),
-            ClimbStatus::ClimbingSlopeSecond => Ok(TileShape::SlopeS)
+            ClimbStatus::ClimbingSlopeSecond => Ok(TileShape::SlopeS),
+            ClimbStatus::ClimbingSlopeStartE => Ok(TileShape::SlopeStartE),
+            ClimbStatus::ClimbingSlopeE => Ok(TileShape::SlopeE),
+            ClimbStatus::ClimbingSlopeStartW => Ok(TileShape::SlopeStartW),
+            ClimbStatus::ClimbingSlopeW => Ok(TileShape::SlopeW),
+            ClimbStatus::ClimbingStairsFirst | ClimbStatus::ClimbingStairsSecond | ClimbStatus::ClimbingStep => Ok(TileShape::Step)
         }
     }
 }
 
-/// Error encountered while climbing
+/// Error encountered while climbing.
+/// Does not know where the climb took place, so it gets converted to a [`crate::map::MapLoadError`] by the caller.
 #[derive(Debug, Clone)]
 pub(crate) struct ClimbingError(pub String);
 impl std::fmt::Display for ClimbingError {
@@ -152,6 +188,20 @@ pub(crate) enum ClimbStatus {
     ClimbingSlopeFirst,
     // Tile is climbing 45-degree southern slope. Second of 2 parts.
     ClimbingSlopeSecond,
+    // Tile is the southern face of a ramp that descends to the east. Next tile is 1 higher (y)
+    ClimbingSlopeStartE,
+    // Tile is on a ramp that descends to the east. Next tile is 1 farther (z)
+    ClimbingSlopeE,
+    // Tile is the southern face of a ramp that descends to the west. Next tile is 1 farther (z)
+    ClimbingSlopeStartW,
+    // Tile is on a ramp that descends to the west. Next tile is 1 farther (z)
+    ClimbingSlopeW,
+    // Tile is climbing stairs. First of 2 steps, each of which is half a tile higher (y) and half a tile farther (z).
+    ClimbingStairsFirst,
+    // Tile is climbing stairs. Second of 2 steps.
+    ClimbingStairsSecond,
+    // Tile is a single step, leaving the floor after it half a tile higher (y) and half a tile farther (z).
+    ClimbingStep,
     // Just encountered a "lip" tile. Tile is treated as floor. Next tile is N tiles below (y) and N tiles farther (z), where N represents how high we were in tiles
     FinishedClimbing
 }
@@ -162,21 +212,15 @@ impl ClimbStatus {
     /// climb status for the current tile.
     pub fn next(
         prev_status: ClimbStatus,
-        tile_type: TileType,
-        tile_x: i32,
-        tile_y: i32,
-        group_layer_name: &str
+        tile_type: TileType
     ) -> Result<Self, ClimbingError> {
 
         // Generates error value
         let make_climbing_error = || -> ClimbingError {
             ClimbingError(format!(
-                "Encountered a {:?} tile while in climb status {:?} on group layer '{}' at {}, {}",
+                "Encountered a {:?} tile while in climb status {:?}",
                 tile_type,
-                prev_status,
-                group_layer_name,
-                tile_x,
-                tile_y
+                prev_status
             ))
         };
 
@@ -189,6 +233,8 @@ impl ClimbStatus {
                 prev_status == Self::ClimbingWallSE ||
                 prev_status == Self::ClimbingWallSW ||
                 prev_status == Self::ClimbingSlopeSecond ||
+                prev_status == Self::ClimbingStairsSecond ||
+                prev_status == Self::ClimbingStep ||
                 prev_status == Self::FinishedClimbing;
             if !is_status_valid {
                 return Err(make_climbing_error());
@@ -199,7 +245,8 @@ impl ClimbStatus {
             if  prev_status == Self::NotClimbing ||
                 prev_status == Self::FinishedClimbing ||
                 prev_status == Self::ClimbingWallS ||
-                prev_status == Self::ClimbingSlopeSecond
+                prev_status == Self::ClimbingSlopeSecond ||
+                prev_status == Self::ClimbingStairsSecond
             {
                 Ok(Self::ClimbingWallS)
             }
@@ -249,12 +296,67 @@ impl ClimbStatus {
                 Self::FinishedClimbing => Self::ClimbingSlopeFirst,
                 Self::ClimbingSlopeFirst => Self::ClimbingSlopeSecond,
                 Self::ClimbingSlopeSecond => Self::ClimbingSlopeFirst,
+                Self::ClimbingSlopeStartE | Self::ClimbingSlopeE => Self::ClimbingSlopeE,
+                Self::ClimbingSlopeStartW | Self::ClimbingSlopeW => Self::ClimbingSlopeW,
                 _ => return Err(make_climbing_error())
             };
             Ok(next_status)
         }
+        else if tile_type == TileType::Stairs {
+            let next_status = match prev_status {
+                Self::NotClimbing => Self::ClimbingStairsFirst,
+                Self::FinishedClimbing => Self::ClimbingStairsFirst,
+                Self::ClimbingStairsFirst => Self::ClimbingStairsSecond,
+                Self::ClimbingStairsSecond => Self::ClimbingStairsFirst,
+                _ => return Err(make_climbing_error())
+            };
+            Ok(next_status)
+        }
+        else if tile_type == TileType::Step {
+            let is_status_valid =
+                prev_status == Self::NotClimbing ||
+                prev_status == Self::FinishedClimbing ||
+                prev_status == Self::ClimbingStep;
+            if !is_status_valid {
+                return Err(make_climbing_error());
+            }
+            Ok(Self::ClimbingStep)
+        }
+        else if tile_type == TileType::SlopeStartE || tile_type == TileType::SlopeStartW {
+            let is_status_valid =
+                prev_status == Self::NotClimbing ||
+                prev_status == Self::FinishedClimbing ||
+                prev_status == Self::ClimbingWallS;
+            if !is_status_valid {
+                return Err(make_climbing_error());
+            }
+            if tile_type == TileType::SlopeStartE {
+                Ok(Self::ClimbingSlopeStartE)
+            }
+            else {
+                Ok(Self::ClimbingSlopeStartW)
+            }
+        }
+        else if tile_type == TileType::SlopeEndE {
+            if prev_status != Self::ClimbingSlopeStartE && prev_status != Self::ClimbingSlopeE {
+                return Err(make_climbing_error());
+            }
+            Ok(Self::NotClimbing)
+        }
+        else if tile_type == TileType::SlopeEndW {
+            if prev_status != Self::ClimbingSlopeStartW && prev_status != Self::ClimbingSlopeW {
+                return Err(make_climbing_error());
+            }
+            Ok(Self::NotClimbing)
+        }
         else if tile_type.is_lip() {
-            if !(prev_status.is_climbing_wall() || prev_status == Self::NotClimbing || prev_status == Self::ClimbingSlopeSecond) {
+            let is_status_valid =
+                prev_status.is_climbing_wall() ||
+                prev_status == Self::NotClimbing ||
+                prev_status == Self::ClimbingSlopeSecond ||
+                prev_status == Self::ClimbingStairsSecond ||
+                prev_status == Self::ClimbingStep;
+            if !is_status_valid {
                 return Err(make_climbing_error());
             }
             Ok(Self::FinishedClimbing)
@@ -269,4 +371,55 @@ impl ClimbStatus {
         self == Self::ClimbingWallSE ||
         self == Self::ClimbingWallSW
     }
-}
\ No newline at end of file
+
+    pub fn is_climbing_step(self) -> bool {
+        self == Self::ClimbingStairsFirst ||
+        self == Self::ClimbingStairsSecond ||
+        self == Self::ClimbingStep
+    }
+}
+
+#[test]
+fn test_climb_slope_e() {
+    let mut climber = Climber::new(Vec3::ZERO, Vec3::splat(16.0));
+    let steps = [
+        (TileType::Wall, TileShape::Wall, Vec3::new(0.0, 0.0, 0.0)),
+        (TileType::SlopeStartE, TileShape::SlopeStartE, Vec3::new(0.0, 16.0, 0.0)),
+        (TileType::Slope, TileShape::SlopeE, Vec3::new(0.0, 32.0, 0.0)),
+        (TileType::SlopeEndE, TileShape::SlopeEndE, Vec3::new(0.0, 32.0, -16.0)),
+        (TileType::Floor, TileShape::Floor, Vec3::new(0.0, 32.0, -32.0))
+    ];
+    for (tile_type, shape, position) in steps {
+        climber.climb(tile_type).unwrap();
+        assert_eq!(shape, climber.tile_shape().unwrap());
+        assert_eq!(position, climber.position());
+    }
+
+    // Ramps have to be ended explicitly
+    let mut climber = Climber::new(Vec3::ZERO, Vec3::splat(16.0));
+    climber.climb(TileType::SlopeStartW).unwrap();
+    climber.climb(TileType::Slope).unwrap();
+    assert!(climber.climb(TileType::Floor).is_err());
+}
+
+#[test]
+fn test_climb_steps() {
+    let mut climber = Climber::new(Vec3::ZERO, Vec3::splat(16.0));
+    let steps = [
+        (TileType::Stairs, Vec3::new(0.0, 0.0, 0.0), false),
+        (TileType::Stairs, Vec3::new(0.0, 8.0, -8.0), true),
+        (TileType::Floor, Vec3::new(0.0, 16.0, -16.0), false),
+        (TileType::Step, Vec3::new(0.0, 16.0, -32.0), false),
+        (TileType::Floor, Vec3::new(0.0, 24.0, -40.0), true)
+    ];
+    for (tile_type, position, is_half_height) in steps {
+        climber.climb(tile_type).unwrap();
+        assert_eq!(position, climber.position());
+        assert_eq!(is_half_height, climber.is_half_height());
+    }
+
+    // Stairs have to be finished before leaving them
+    let mut climber = Climber::new(Vec3::ZERO, Vec3::splat(16.0));
+    climber.climb(TileType::Stairs).unwrap();
+    assert!(climber.climb(TileType::Floor).is_err());
+}
@@ -1,106 +1,333 @@
 use bevy::prelude::*;
+use bevy::utils::HashMap;
 use tiled::*;
+use std::ops::Range;
 use std::result::Result;
 
-use crate::physics::TerrainPiece;
-use crate::map::{TileType, TileGraphics, TileMeshData, CurrentMapGraphics, CurrentMap, ClimbingError, Climber, ClimbStatus };
+use crate::physics::{TerrainPiece, Coords};
+use crate::map::{TileType, TileGraphics, TileMeshData, CurrentMapGraphics, CurrentMap, ClimbingError, Climber, ClimbStatus, MapObject, MapLoadError, MapSettings, TileFrame, TilesetAtlas, TileBounds };
 
 // Used to push graphics closer to the camera by a tiny bit to get correct overlapping
 const DEPTH_EPSILON: f32 = 0.001;
 
+// Width and height of the chunks Tiled stores infinite layers in, measured in tiles
+const TILED_CHUNK_SIZE: i32 = 16;
+
 // Reads contents of tiled map, parses/validates it, and populates collision data (current_map) and graphics (current_map_graphics).
+// Fails with the first problem found.
 pub(crate) fn process_tiled_map(
     tiled_map: &tiled::Map,
     flip_y: bool,
     current_map: &mut CurrentMap,
     current_map_graphics: &mut CurrentMapGraphics
-) -> Result<(), ClimbingError> {
+) -> Result<(), MapLoadError> {
+    let placement = MapPlacement::new(tiled_map);
+    process_placed_tiled_map(tiled_map, flip_y, &placement, current_map, current_map_graphics)
+}
+
+// Same as process_tiled_map, but for a map that is one of many placed within a world.
+// Can be called once for every map of the world with the same current_map and current_map_graphics.
+pub(crate) fn process_placed_tiled_map(
+    tiled_map: &tiled::Map,
+    flip_y: bool,
+    placement: &MapPlacement,
+    current_map: &mut CurrentMap,
+    current_map_graphics: &mut CurrentMapGraphics
+) -> Result<(), MapLoadError> {
+    let mut errors = Vec::new();
+    traverse_tiled_map(tiled_map, flip_y, placement, None, current_map, current_map_graphics, &mut errors);
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(())
+    }
+}
+
+// Same as process_placed_tiled_map, but only traverses the columns of tiles within the world that are in range.
+// Used to regenerate part of a map that was edited after spawning. Objects are skipped, as they were already announced.
+pub(crate) fn process_tiled_map_columns(
+    tiled_map: &tiled::Map,
+    flip_y: bool,
+    placement: &MapPlacement,
+    columns: Range<i32>,
+    current_map: &mut CurrentMap,
+    current_map_graphics: &mut CurrentMapGraphics
+) -> Result<(), MapLoadError> {
+    let mut errors = Vec::new();
+    traverse_tiled_map(tiled_map, flip_y, placement, Some(&columns), current_map, current_map_graphics, &mut errors);
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(())
+    }
+}
+
+/// Where a map is placed within a world made of many maps.
+/// A map on its own is placed at the origin of the world.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct MapPlacement {
+    /// Position of the map's top-left tile within the world, in tiles
+    pub tile_offset: IVec2,
+    /// Row of the world, in tiles, that sits at z = 0
+    pub bottom_row: i32,
+    /// Index of the map's first tileset among the tilesets of every map in the world
+    pub tileset_offset: usize
+}
+
+impl MapPlacement {
+
+    /// Placement of a map on its own
+    pub fn new(tiled_map: &Map) -> Self {
+        Self {
+            tile_offset: IVec2::ZERO,
+            bottom_row: map_tile_bounds(tiled_map).max.y - 1,
+            tileset_offset: 0
+        }
+    }
+
+    // Offset to apply to the world positions of the map's tiles
+    fn origin(&self, tiled_map: &Map, bounds: &TileBounds) -> Vec3 {
+        let (tw, th) = (tiled_map.tile_width as f32, tiled_map.tile_height as f32);
+        let map_bottom_row = bounds.max.y - 1 + self.tile_offset.y;
+        Vec3::new(
+            self.tile_offset.x as f32 * tw,
+            0.0,
+            -((self.bottom_row - map_bottom_row) as f32) * th
+        )
+    }
+}
+
+/// Runs a tiled map through the same pipeline used when loading it, without an app running.
+/// Unlike loading, keeps going after a problem is found, and returns every problem in the map.
+/// Image collection tilesets are checked against the images their tiles reference, rather than against a built atlas.
+pub fn check_tiled_map(tiled_map: &Map, flip_y: bool) -> Vec<MapLoadError> {
+    let mut current_map = CurrentMap::new("", Handle::default());
+    let mut current_map_graphics = CurrentMapGraphics::default();
+    for tileset in tiled_map.tilesets() {
+        let atlas = match tileset.image {
+            Some(_) => None,
+            None => Some(placeholder_atlas(tileset))
+        };
+        current_map_graphics.tileset_atlases.push(atlas);
+    }
+    let mut errors = Vec::new();
+    let placement = MapPlacement::new(tiled_map);
+    traverse_tiled_map(tiled_map, flip_y, &placement, None, &mut current_map, &mut current_map_graphics, &mut errors);
+
+    // Bad settings don't stop a map from loading, as it falls back to the defaults, but they're still worth reporting
+    if let Err(error) = MapSettings::from_properties(&tiled_map.properties, &current_map.name) {
+        errors.push(error);
+    }
+    errors
+}
 
+// Atlas of an image collection tileset that was never packed, where every tile with an image sits at the origin
+pub(crate) fn placeholder_atlas(tileset: &Tileset) -> TilesetAtlas {
+    let rects: HashMap<_, _> = tileset
+        .tiles()
+        .filter_map(|(tile_id, tile)| {
+            let image = tile.image.as_ref()?;
+            let max = Vec2::new(image.width as f32, image.height as f32);
+            Some((tile_id, bevy::sprite::Rect { min: Vec2::ZERO, max }))
+        })
+        .collect();
+    let size = rects
+        .values()
+        .fold(Vec2::ONE, |size, rect| size.max(rect.max));
+    TilesetAtlas { size, rects }
+}
+
+// Traverses the tiled map, populating current_map and current_map_graphics.
+// Problems are pushed to errors. Columns are abandoned at the first problem in them, and layers that can't be traversed are skipped.
+// When columns are specified, only traverses those columns of the world, and skips objects.
+fn traverse_tiled_map(
+    tiled_map: &tiled::Map,
+    flip_y: bool,
+    placement: &MapPlacement,
+    columns: Option<&Range<i32>>,
+    current_map: &mut CurrentMap,
+    current_map_graphics: &mut CurrentMapGraphics,
+    errors: &mut Vec<MapLoadError>
+) {
+
+    // Metadata of maps in a world is merged, with maps traversed earlier taking precedence
     let mut flattened_layer_index = 0;
+    let bounds = map_tile_bounds(tiled_map);
+    let origin = placement.origin(tiled_map, &bounds);
+    let placed_bounds = TileBounds::new(bounds.min + placement.tile_offset, bounds.max + placement.tile_offset);
+    current_map.bounds = current_map.bounds.union(placed_bounds);
+    current_map.tile_size = Vec3::new(tiled_map.tile_width as f32, tiled_map.tile_height as f32, tiled_map.tile_height as f32);
+    for (name, value) in &tiled_map.properties {
+        current_map.properties.entry(name.clone()).or_insert_with(|| value.clone());
+    }
+
+    // Determines which tilesets have their images flipped vertically.
+    // Tileset property takes precedence over map property, which takes precedence over the config.
+    let map_flip_y = get_bool_property(&tiled_map.properties, "flip_y").unwrap_or(flip_y);
+    let tileset_flip_y: Vec<bool> = tiled_map
+        .tilesets()
+        .iter()
+        .map(|tileset| get_bool_property(&tileset.properties, "flip_y").unwrap_or(map_flip_y))
+        .collect();
+
+    // Surfaces of every group, where the topmost surface of a tile wins.
+    // Root object layers are placed once every group was climbed, as they may come before the groups they sit on.
+    let mut map_surfaces: HashMap<(i32, i32), TileSurface> = HashMap::default();
+    let mut root_object_layers = Vec::new();
 
     // For all group layers in the root...
     for root_layer in tiled_map.layers() {
         match &root_layer.layer_type() {
             LayerType::GroupLayer(group_layer) => {
 
-                // Splits the group layer between the terrain layers and the meta layers
-                let (terrain_layers, meta_layers) = split_group_layer(group_layer);
+                // Splits the group layer between the terrain layers, the meta layers and the object layers
+                let (terrain_layers, meta_layers, object_layers) = split_group_layer(group_layer, &root_layer.name, errors);
 
                 // Process those sub layers
                 log::trace!("Processing group layer {}", &root_layer.name);
-                let offset_y = 0;
+                let settings = match GroupSettings::from_properties(&root_layer.properties, &root_layer.name) {
+                    Ok(settings) => settings,
+                    Err(error) => {
+                        errors.push(error);
+                        continue;
+                    }
+                };
+                let mut surfaces = HashMap::default();
                 process_sub_layers(
                     &meta_layers,
                     &terrain_layers,
-                    offset_y,
+                    &settings,
                     tiled_map,
-                    flip_y,
+                    &bounds,
+                    placement,
+                    columns,
+                    origin,
+                    &tileset_flip_y,
                     &root_layer.name,
                     current_map,
                     current_map_graphics,
-                    flattened_layer_index
-                )?;
+                    flattened_layer_index,
+                    &mut surfaces,
+                    errors
+                );
                 flattened_layer_index += terrain_layers.len();
+                for (tile, surface) in &surfaces {
+                    let is_topmost = map_surfaces
+                        .get(tile)
+                        .map_or(true, |map_surface| surface.position.y > map_surface.position.y);
+                    if is_topmost {
+                        map_surfaces.insert(*tile, *surface);
+                    }
+                }
+
+                // Places the group's objects on top of the surfaces that were just climbed
+                if columns.is_some() {
+                    continue;
+                }
+                for (object_layer_name, object_layer) in &object_layers {
+                    let layer_name = format!("{}/{}", &root_layer.name, object_layer_name);
+                    process_object_layer(object_layer, &layer_name, tiled_map, current_map, |x, y| {
+                        surfaces
+                            .get(&(x, y))
+                            .copied()
+                            .unwrap_or_else(|| TileSurface::flat(x, y, &bounds, origin, tiled_map))
+                    });
+                }
             },
-            _ => return Err(ClimbingError("All root layers must be group layers".to_owned()))
+            LayerType::ObjectLayer(_) if columns.is_some() => {},
+            LayerType::ObjectLayer(object_layer) => root_object_layers.push((root_layer.name.clone(), object_layer.clone())),
+            _ => errors.push(MapLoadError::LayerStructure {
+                layer: root_layer.name.clone(),
+                message: "Root layers must be group layers or object layers".to_owned()
+            })
         }
     }
-    Ok(())
+
+    // Objects outside of group layers sit on the topmost surface of every group
+    for (layer_name, object_layer) in &root_object_layers {
+        log::trace!("Processing object layer {}", layer_name);
+        process_object_layer(object_layer, layer_name, tiled_map, current_map, |x, y| {
+            map_surfaces
+                .get(&(x, y))
+                .copied()
+                .unwrap_or_else(|| TileSurface::flat(x, y, &bounds, origin, tiled_map))
+        });
+    }
 }
 
 // Processes the sub layers of a group layer
 fn process_sub_layers(
     m_layers: &[MetaLayer],                                     // Group meta layers
     t_layers: &[TileLayer],                                     // Group terrain layers
-    offset_y: i32,                                              // Group offset y (measured in tiles, not pixels)
+    settings: &GroupSettings,                                   // Settings of the group
     map: &Map,                                                  // Map itself
-    flip_y: bool,
+    bounds: &TileBounds,                                        // Tiles to traverse
+    placement: &MapPlacement,                                   // Where the map is placed within the world
+    columns: Option<&Range<i32>>,                               // Columns of the world to traverse, or None for all of them
+    origin: Vec3,                                               // Offset of the map's tiles in world space
+    tileset_flip_y: &[bool],                                    // Which tilesets have vertically flipped images, by tileset index
     group_layer_name: &str,
     current_map: &mut CurrentMap,
     current_map_graphics: &mut CurrentMapGraphics,
     flattened_layer_index: usize,
-) -> Result<(), ClimbingError> {
+    surfaces: &mut HashMap<(i32, i32), TileSurface>,            // Surfaces climbed, keyed by tile x/y
+    errors: &mut Vec<MapLoadError>                              // Problems found
+) {
 
     // For all columns in the group...
-    let (w, h) = (map.width, map.height);
     let (tw, th) = (map.tile_width as f32, map.tile_height as f32);
     let tile_size = Vec3::new(tw, th, th);
-    for x in 0..w {
+    let offset_y = settings.elevation;
+    for x in bounds.min.x..bounds.max.x {
+        if columns.map_or(false, |columns| !columns.contains(&(x + placement.tile_offset.x))) {
+            continue;
+        }
 
-        // Make climbers at the bottom of the vertical strip...
-        let climber_pos = Vec2::new(x as f32 * tw, offset_y as f32 * th);
+        // Make climbers at the bottom of the vertical strip.
+        // Elevated climbers are pushed toward the camera by as much as they are raised, so that tiles still appear where they were drawn.
+        let climber_pos = origin + Vec3::new(x as f32 * tw, offset_y as f32 * th, offset_y as f32 * th);
         let mut geom_climber = Climber::new(climber_pos, tile_size);
         let mut coll_climber = Climber::new(climber_pos, tile_size);
 
         // Traverse the strip from bottom to top
-        let x = x as i32;
-        for y in (0..h).rev() {
-
-            // Gets meta tile (optional) and terrain tiles at (x, y)
-            let y = y as i32;
+        for y in (bounds.min.y..bounds.max.y).rev() {
 
             // "Climb" the current tile at x, y
-            process_tiles_at(
+            let is_occupied = process_tiles_at(
                 m_layers,
                 t_layers,
                 x,
                 y,
                 &mut geom_climber,
                 &mut coll_climber,
-                flip_y,
+                placement,
+                tileset_flip_y,
                 group_layer_name,
                 current_map,
                 current_map_graphics,
                 tile_size.y,
-                offset_y,
+                settings,
                 flattened_layer_index
-            )?;
+            );
+            let is_occupied = match is_occupied {
+                Ok(is_occupied) => is_occupied,
+                Err(error) => {
+                    // Climbers are in an unknown state, so the rest of the column can't be trusted
+                    errors.push(error);
+                    break;
+                }
+            };
+            if is_occupied {
+                surfaces.insert((x, y), TileSurface {
+                    position: coll_climber.position(),
+                    status: coll_climber.climb_status()
+                });
+            }
         }
     }
-    Ok(())
 }
 
-// Processes the tiles of a sub layer at a specific X/Y location (tile_x, tile_y)
+// Processes the tiles of a sub layer at a specific X/Y location (tile_x, tile_y).
+// Returns true if the location was occupied, which is always the case for groups at ground level.
+// Elevated groups and bridges only occupy locations that have tiles in them.
 fn process_tiles_at<'map>(
     meta_layers: &[MetaLayer<'map>],
     terrain_layers: &[TileLayer],
@@ -108,104 +335,216 @@ fn process_tiles_at<'map>(
     tile_y: i32,
     geom_climber: &mut Climber,
     coll_climber: &mut Climber,
-    flip_y: bool,
+    placement: &MapPlacement,
+    tileset_flip_y: &[bool],
     group_layer_name: &str,
     current_map: &mut CurrentMap,
     current_map_graphics: &mut CurrentMapGraphics,
     _tile_height: f32,
-    offset_y: i32,
+    settings: &GroupSettings,
     flattened_layer_index: usize
-) -> Result<(), ClimbingError> {
+) -> Result<bool, MapLoadError> {
 
     // Gets first meta tile at tile_x, tile_y and all terrain tiles found at tile_x, tile_y of current group layer
     let meta_tile = meta_layers
         .iter()
         .flat_map(|m_layer| m_layer.get_tile(tile_x, tile_y))
         .next();
-    let terrain_tiles = terrain_layers
+    let terrain_tiles: Vec<LayerTile> = terrain_layers
         .iter()
-        .flat_map(|layer| layer.get_tile(tile_x, tile_y));
+        .flat_map(|layer| layer.get_tile(tile_x, tile_y))
+        .collect();
+    let offset_y = settings.elevation;
+    let is_occupied = (offset_y == 0 && !settings.bridge) || meta_tile.is_some() || !terrain_tiles.is_empty();
+
+    // Attaches the location of the climb to climbing errors
+    let to_map_error = |err: ClimbingError| MapLoadError::Climbing {
+        layer: group_layer_name.to_owned(),
+        x: tile_x,
+        y: tile_y,
+        message: err.0
+    };
 
-    // Gets the geom/coll types of current meta tile and uses it to "climb" both the collision and geometry
-    let (geom_type, coll_type) = meta_tile
-        .map(|tile| tile.get_types())
-        .unwrap_or((TileType::Floor, TileType::Floor));
-    geom_climber.climb(geom_type, tile_x, tile_y, group_layer_name)?;
-    coll_climber.climb(coll_type, tile_x, tile_y, group_layer_name)?;
+    // Gets the geom/coll types of current meta tile and uses it to "climb" both the collision and geometry.
+    // Occupied tiles edited after spawning use their new type for both.
+    let world_tile = IVec2::new(tile_x, tile_y) + placement.tile_offset;
+    let meta_override = current_map.meta_overrides.get(&world_tile).copied().filter(|_| is_occupied);
+    let (geom_type, coll_type) = match (meta_override, meta_tile) {
+        (Some(tile_type), _) => (tile_type, tile_type),
+        (None, Some(tile)) => tile.get_types(group_layer_name, tile_x, tile_y)?,
+        (None, None) => (TileType::Floor, TileType::Floor)
+    };
+    geom_climber.climb(geom_type).map_err(to_map_error)?;
+    coll_climber.climb(coll_type).map_err(to_map_error)?;
+    if meta_tile.is_some() || meta_override.is_some() {
+        current_map.meta_tiles.insert(world_tile, coll_type);
+    }
 
     // For all terrain tiles in the current group layer...
-    for (layer_index, t_tile) in terrain_tiles.enumerate() {
+    for (layer_index, t_tile) in terrain_tiles.into_iter().enumerate() {
 
-        // Finds tileset, and computes mesh data
+        // Finds tileset, and computes mesh data.
+        // Graphics of every map in a world share one list of tilesets.
         let tileset_index = t_tile.tileset_index();
         let tileset = t_tile.get_tileset();
-        let tile_mesh_data = get_tile_mesh_data(&tileset, t_tile.id(), flip_y);
+        let flip_y = tileset_flip_y[tileset_index];
+        let tileset_index = tileset_index + placement.tileset_offset;
+        let atlas = current_map_graphics.tileset_atlases[tileset_index].as_ref();
+        let mesh_data_of = |tile_id: u32| -> Result<TileMeshData, MapLoadError> {
+            let mesh_data = get_tile_mesh_data(&tileset, tile_id, flip_y, atlas).ok_or_else(|| MapLoadError::MissingTileImage {
+                tileset: tileset.name.clone(),
+                tile_id,
+                layer: group_layer_name.to_owned(),
+                x: tile_x,
+                y: tile_y
+            })?;
+            Ok(TileMeshData {
+                flip_h: t_tile.flip_h,
+                flip_v: t_tile.flip_v,
+                flip_d: t_tile.flip_d,
+                ..mesh_data
+            })
+        };
+        let tile_mesh_data = mesh_data_of(t_tile.id())?;
+
+        // Gets frames of the tile if it's animated
+        let frames = t_tile
+            .get_tile()
+            .and_then(|tile| tile.animation.clone())
+            .unwrap_or_default()
+            .iter()
+            .map(|frame| Ok(TileFrame {
+                mesh_data: mesh_data_of(frame.tile_id)?,
+                duration: frame.duration
+            }))
+            .collect::<Result<Vec<TileFrame>, MapLoadError>>()?;
         let flattened_layer_index = flattened_layer_index + layer_index;
         let depth_offset = Vec3::new(0.0, DEPTH_EPSILON, DEPTH_EPSILON) * flattened_layer_index as f32;
 
         // Write to current_map_graphics
-        let geom_shape = geom_climber.tile_shape()?;
-        current_map_graphics.add_tile(TileGraphics {
+        let geom_shape = geom_climber.tile_shape().map_err(to_map_error)?;
+        current_map_graphics.add_animated_tile(TileGraphics {
             tileset_index: tileset_index as u32,
             translation: geom_climber.position() + depth_offset,
             mesh_data: tile_mesh_data,
             shape: geom_shape
-        });
+        }, &frames);
     }
 
     // Write to current_map
-    match coll_climber.climb_status() {
-        ClimbStatus::NotClimbing => {
-            let mut coords = coll_climber.coords();
+    if !is_occupied {
+        return Ok(false);
+    }
+    // Bridges are a single tile thick, so that there's room to walk under them
+    let fill_bottom = |coords: Coords| if settings.bridge { coords.y - 1 } else { offset_y - 1 };
+    // Writes a piece with solid ground beneath it, unless on a bridge
+    let write_piece = |current_map: &mut CurrentMap, piece: TerrainPiece, mut coords: Coords| {
+        current_map.set_terrain_piece(piece, coords);
+        if !settings.bridge {
             while coords.y >= offset_y {
                 coords.y -= 1;
                 current_map.set_terrain_piece(TerrainPiece::Cuboid, coords);
             }
         }
+    };
+    // East ramps are drawn from their high side, so their pieces are one below the climber
+    let slope_e_coords = || {
+        let mut coords = coll_climber.coords();
+        coords.y -= 1;
+        coords
+    };
+    match coll_climber.climb_status() {
+        ClimbStatus::NotClimbing => match coll_climber.prev_status() {
+            ClimbStatus::ClimbingSlopeStartE | ClimbStatus::ClimbingSlopeE => {
+                write_piece(current_map, TerrainPiece::SlopeE, slope_e_coords());
+            }
+            ClimbStatus::ClimbingSlopeStartW | ClimbStatus::ClimbingSlopeW => {
+                write_piece(current_map, TerrainPiece::SlopeW, coll_climber.coords());
+            }
+            _ => {
+                // Floors half a tile up, such as after a step, stand on a half-height piece
+                let mut coords = coll_climber.coords();
+                if coll_climber.is_half_height() {
+                    current_map.set_terrain_piece(TerrainPiece::HalfCuboid, coords);
+                }
+                let bottom = fill_bottom(coords);
+                while coords.y > bottom {
+                    coords.y -= 1;
+                    current_map.set_terrain_piece(TerrainPiece::Cuboid, coords);
+                }
+            }
+        }
         ClimbStatus::ClimbingWallS | ClimbStatus::ClimbingWallSE | ClimbStatus::ClimbingWallSW => {
             current_map.set_terrain_piece(TerrainPiece::Cuboid, coll_climber.coords());
         }
         ClimbStatus::ClimbingSlopeFirst => {
-            current_map.set_terrain_piece(TerrainPiece::Slope, coll_climber.coords());
-            let mut coords = coll_climber.coords();
-            while coords.y >= offset_y {
-                coords.y -= 1;
-                current_map.set_terrain_piece(TerrainPiece::Cuboid, coords);
-            }
+            write_piece(current_map, TerrainPiece::Slope, coll_climber.coords());
         }
         ClimbStatus::ClimbingSlopeSecond => {
             // Assumed that ClimbingSlopeFirst case handled writing slope correctly.
         }
+        ClimbStatus::ClimbingStairsFirst => {
+            write_piece(current_map, TerrainPiece::Stairs, coll_climber.coords());
+        }
+        ClimbStatus::ClimbingStairsSecond => {
+            // Assumed that ClimbingStairsFirst case handled writing stairs correctly.
+        }
+        ClimbStatus::ClimbingStep => {
+            // A step that starts half a tile up finishes the stairs started by the step before it
+            let piece = if coll_climber.is_half_height() { TerrainPiece::Stairs } else { TerrainPiece::HalfCuboid };
+            write_piece(current_map, piece, coll_climber.coords());
+        }
+        ClimbStatus::ClimbingSlopeStartE => {
+            // Southern face of the ramp. Its top is written by the ClimbingSlopeE case.
+        }
+        ClimbStatus::ClimbingSlopeE => {
+            write_piece(current_map, TerrainPiece::SlopeE, slope_e_coords());
+        }
+        ClimbStatus::ClimbingSlopeStartW | ClimbStatus::ClimbingSlopeW => {
+            write_piece(current_map, TerrainPiece::SlopeW, coll_climber.coords());
+        }
         ClimbStatus::FinishedClimbing => {
             let mut coords = coll_climber.coords();
             let mut next_coords = coll_climber.next_coords();
             coords.y -= 1;
             next_coords.y -= 1;
 
-            // Writes 'L' shape to current_map
-            while coords.y > next_coords.y {
-                current_map.set_terrain_piece(TerrainPiece::Cuboid, coords);
-                coords.y -= 1;
-            }
-            while coords.z > next_coords.z {
-                current_map.set_terrain_piece(TerrainPiece::Cuboid, coords);
-                coords.z -= 1;
+            // Writes 'L' shape to current_map, unless on a bridge, which leaves room to walk under its lip
+            if !settings.bridge {
+                while coords.y > next_coords.y {
+                    current_map.set_terrain_piece(TerrainPiece::Cuboid, coords);
+                    coords.y -= 1;
+                }
+                while coords.z > next_coords.z {
+                    current_map.set_terrain_piece(TerrainPiece::Cuboid, coords);
+                    coords.z -= 1;
+                }
             }
+
+            // Writes lip on top of the cliff
+            current_map.set_terrain_piece(TerrainPiece::Lip, coll_climber.coords());
         }
     }
 
     // Done
-    Ok(())
+    Ok(true)
 }
 
-/// Splits group layer between
-fn split_group_layer<'map>(group_layer: &'map GroupLayer<'map>) -> (Vec<TileLayer<'map>>, Vec<MetaLayer<'map>>){
+/// Splits group layer between terrain layers, meta layers and object layers (paired with their names).
+/// Sub layers that are none of those are pushed to errors and left out.
+fn split_group_layer<'map>(
+    group_layer: &'map GroupLayer<'map>,
+    group_layer_name: &str,
+    errors: &mut Vec<MapLoadError>
+) -> (Vec<TileLayer<'map>>, Vec<MetaLayer<'map>>, Vec<(String, ObjectLayer<'map>)>) {
 
     // Goes through sub layers and splits them
     let mut terrain_layers = Vec::new();
     let mut meta_layers = Vec::new();
+    let mut object_layers = Vec::new();
     for sub_layer in group_layer.layers() {
         let sub_properties = &sub_layer.properties;
+        let sub_layer_name = sub_layer.name.clone();
         match sub_layer.layer_type() {
             LayerType::TileLayer(sub_layer) => {
                 let tile_layer_type = get_string_property(sub_properties, "type").unwrap_or("terrain");
@@ -214,15 +553,171 @@ fn split_group_layer<'map>(group_layer: &'map GroupLayer<'map>) -> (Vec<TileLaye
                     "geom_coll" => meta_layers.push(MetaLayer::GeomColl(sub_layer)),
                     "geom" => meta_layers.push(MetaLayer::Geom(sub_layer)),
                     "coll" => meta_layers.push(MetaLayer::Coll(sub_layer)),
-                    _ => panic!("Unexpected tile layer type '{}'", tile_layer_type)
+                    _ => errors.push(MapLoadError::LayerStructure {
+                        layer: format!("{}/{}", group_layer_name, &sub_layer_name),
+                        message: format!("Unexpected tile layer type '{}'", tile_layer_type)
+                    })
                 }
             },
-            _ => panic!("Sub layer must be a tile layer")
+            LayerType::ObjectLayer(object_layer) => object_layers.push((sub_layer_name, object_layer)),
+            _ => errors.push(MapLoadError::LayerStructure {
+                layer: format!("{}/{}", group_layer_name, &sub_layer_name),
+                message: "Sub layer must be a tile layer or an object layer".to_owned()
+            })
         }
     }
 
     // Returns split data
-    (terrain_layers, meta_layers)
+    (terrain_layers, meta_layers, object_layers)
+}
+
+// Converts the objects of an object layer into map objects.
+// surface_at gets the surface of the tile at the specified tile x/y, which is used to determine the world position of an object.
+fn process_object_layer(
+    object_layer: &ObjectLayer,
+    layer_name: &str,
+    map: &Map,
+    current_map: &mut CurrentMap,
+    surface_at: impl Fn(i32, i32) -> TileSurface
+) {
+    let (tw, th) = (map.tile_width as f32, map.tile_height as f32);
+    let tile_size = Vec3::new(tw, th, th);
+    for object in object_layer.objects() {
+
+        // Finds tile the object was placed on, and where on that tile it was placed
+        let (tile_x, tile_y) = ((object.x / tw).floor(), (object.y / th).floor());
+        let fraction = Vec2::new(object.x / tw - tile_x, object.y / th - tile_y);
+        let surface = surface_at(tile_x as i32, tile_y as i32);
+
+        // Size of the object, if it has any
+        let size = match object.shape {
+            ObjectShape::Rect { width, height } => Vec2::new(width, height),
+            ObjectShape::Ellipse { width, height } => Vec2::new(width, height),
+            _ => Vec2::ZERO
+        };
+
+        current_map.objects.push(MapObject {
+            id: object.id(),
+            name: object.name.clone(),
+            typ: object.user_type.clone(),
+            position: surface.point(fraction, tile_size),
+            size,
+            layer_name: layer_name.to_owned(),
+            properties: object.properties.clone()
+        });
+    }
+}
+
+/// Settings of a group layer, read from its custom properties
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+struct GroupSettings {
+    /// Height, in tiles, that the group's climbers start at
+    elevation: i32,
+    /// If true, floors only write a single tile of collision beneath them instead of filling down to the group's elevation,
+    /// and tiles without graphics or meta tiles write nothing. Allows the group to overlap with terrain beneath it.
+    bridge: bool
+}
+
+impl GroupSettings {
+    fn from_properties(properties: &Properties, group_layer_name: &str) -> Result<Self, MapLoadError> {
+        let elevation = match properties.get("elevation") {
+            Some(PropertyValue::IntValue(elevation)) => *elevation,
+            None => 0,
+            Some(_) => return Err(MapLoadError::LayerStructure {
+                layer: group_layer_name.to_owned(),
+                message: "Property 'elevation' must be an int".to_owned()
+            })
+        };
+        let bridge = match properties.get("bridge") {
+            Some(PropertyValue::BoolValue(bridge)) => *bridge,
+            None => false,
+            Some(_) => return Err(MapLoadError::LayerStructure {
+                layer: group_layer_name.to_owned(),
+                message: "Property 'bridge' must be a bool".to_owned()
+            })
+        };
+        Ok(Self { elevation, bridge })
+    }
+}
+
+/// Tiles occupied by a map.
+/// For finite maps, this is just the size of the map. For infinite maps, it's the union of all chunks of all tile layers.
+/// In both cases, the bottom row of the bounds sits at z = 0 in world space.
+pub(crate) fn map_tile_bounds(map: &Map) -> TileBounds {
+    if !map.infinite() {
+        return TileBounds::new(IVec2::ZERO, IVec2::new(map.width as i32, map.height as i32));
+    }
+    fn layer_bounds<'map>(layers: impl Iterator<Item = Layer<'map>>) -> TileBounds {
+        let mut bounds = TileBounds::default();
+        for layer in layers {
+            match layer.layer_type() {
+                LayerType::TileLayer(TileLayer::Infinite(infinite_layer)) => {
+                    for ((chunk_x, chunk_y), _) in infinite_layer.chunks() {
+                        let min = IVec2::new(chunk_x, chunk_y) * TILED_CHUNK_SIZE;
+                        bounds = bounds.union(TileBounds::new(min, min + TILED_CHUNK_SIZE));
+                    }
+                }
+                LayerType::GroupLayer(group_layer) => bounds = bounds.union(layer_bounds(group_layer.layers())),
+                _ => {}
+            }
+        }
+        bounds
+    }
+    layer_bounds(map.layers())
+}
+
+/// Surface of a tile that was climbed.
+/// Used for placing objects on top of the terrain.
+#[derive(Debug, Copy, Clone)]
+struct TileSurface {
+    position: Vec3,
+    status: ClimbStatus
+}
+
+impl TileSurface {
+
+    /// Surface of a tile on flat ground
+    fn flat(tile_x: i32, tile_y: i32, bounds: &TileBounds, origin: Vec3, map: &Map) -> Self {
+        let (tw, th) = (map.tile_width as f32, map.tile_height as f32);
+        let bottom = bounds.max.y - 1;
+        Self {
+            position: origin + Vec3::new(tile_x as f32 * tw, 0.0, -((bottom - tile_y) as f32) * th),
+            status: ClimbStatus::NotClimbing
+        }
+    }
+
+    /// World position of a point on this surface.
+    /// Fraction is the position of the point within the tile, where (0, 0) is the top-left of the tile in the map file.
+    fn point(&self, fraction: Vec2, tile_size: Vec3) -> Vec3 {
+        let x = fraction.x * tile_size.x;
+        let up = 1.0 - fraction.y;
+        let offset = match self.status {
+            ClimbStatus::ClimbingWallS | ClimbStatus::ClimbingWallSE | ClimbStatus::ClimbingWallSW => {
+                Vec3::new(x, up * tile_size.y, 0.0)
+            }
+            ClimbStatus::ClimbingSlopeFirst | ClimbStatus::ClimbingSlopeSecond => {
+                Vec3::new(x, up * tile_size.y * 0.5, -up * tile_size.z * 0.5)
+            }
+            ClimbStatus::ClimbingSlopeE => {
+                Vec3::new(x, -fraction.x * tile_size.y, -(fraction.x + up) * tile_size.z)
+            }
+            ClimbStatus::ClimbingSlopeW => {
+                Vec3::new(x, fraction.x * tile_size.y, (fraction.x - up) * tile_size.z)
+            }
+            ClimbStatus::ClimbingSlopeStartE | ClimbStatus::ClimbingSlopeStartW => {
+                Vec3::new(x, up * tile_size.y, 0.0)
+            }
+            ClimbStatus::ClimbingStairsFirst | ClimbStatus::ClimbingStairsSecond | ClimbStatus::ClimbingStep => {
+                let riser = up.min(0.5);
+                let tread = (up - 0.5).max(0.0);
+                Vec3::new(x, riser * tile_size.y, -tread * tile_size.z)
+            }
+            ClimbStatus::NotClimbing | ClimbStatus::FinishedClimbing => {
+                Vec3::new(x, 0.0, -up * tile_size.z)
+            }
+        };
+        self.position + offset
+    }
 }
 
 
@@ -240,40 +735,91 @@ fn get_string_property<'a>(properties: &'a Properties, key: &str) -> Option<&'a
     }
 }
 
-fn get_tile_mesh_data(tileset: &Tileset, tile_id: u32, flip_y: bool) -> TileMeshData {
+// Helper function that assumes a property is a bool
+fn get_bool_property(properties: &Properties, key: &str) -> Option<bool> {
+    match properties.get(key) {
+        Some(PropertyValue::BoolValue(value)) => Some(*value),
+        _ => None
+    }
+}
 
+// Computes mesh data of a tile, using the atlas of the tileset if it's an image collection.
+// None if the tile has no image.
+fn get_tile_mesh_data(tileset: &Tileset, tile_id: u32, flip_y: bool, atlas: Option<&TilesetAtlas>) -> Option<TileMeshData> {
     let ts = tileset;                                                       // Tileset
-    let img = ts.image.as_ref().expect("Tileset must have a single image"); // Tileset image
-    let tsm = tileset.margin as f32;                                        // Tileset margin
-    let tssp = tileset.spacing as f32;                                      // Tileset spacing
-    let (tiw, tih) = (ts.tile_width as f32, ts.tile_height as f32);         // Tile width / height
-    let (tixi, tiyi) = (tile_id % ts.columns, tile_id / ts.columns);        // Tile x / y (ints)
-    let (tix, tiy) = (tixi as f32 * tiw, tiyi as f32 * tih);                // Tile x / y (floats)
-    let tss = Vec2::new(img.width as f32, img.height as f32);               // Tileset size
-
-    // Creates UV coords
-    let tsm = Vec2::new(tsm, tsm);          // Tileset margin
-    let (uv1, uv2, uv3, uv4) = if !flip_y {
-        let tip = Vec2::new(tix, tiy) + Vec2::new(0.0, tih);     // Tile position
-        let tisp = Vec2::new(tixi as f32, tiyi as f32) * tssp;   // Tile spacing
-        let uv1 = tip + tsm + tisp;
-        let uv2 = uv1 + Vec2::new(tiw, 0.0);
-        let uv3 = uv1 + Vec2::new(tiw, -tih);
-        let uv4 = uv1 + Vec2::new(0.0, -tih);
-        (uv1, uv2, uv3, uv4)
-    }
-    else {
-        panic!("Not yet implemented");
+    let (tile_size, [uv1, uv2, uv3, uv4]) = match (&ts.image, atlas) {
+        (Some(img), _) => {
+            let tile_size = Vec2::new(ts.tile_width as f32, ts.tile_height as f32);
+            let image_size = Vec2::new(img.width as f32, img.height as f32);
+            let uvs = tile_uvs(
+                tile_id,
+                ts.columns,
+                tile_size,
+                ts.margin as f32,
+                ts.spacing as f32,
+                image_size,
+                flip_y
+            );
+            (tile_size, uvs)
+        }
+        (None, Some(atlas)) => {
+            let rect = atlas.rects.get(&tile_id)?;
+            let (min, max) = (rect.min / atlas.size, rect.max / atlas.size);
+            let uvs = [
+                Vec2::new(min.x, max.y),
+                Vec2::new(max.x, max.y),
+                Vec2::new(max.x, min.y),
+                Vec2::new(min.x, min.y)
+            ];
+            (rect.max - rect.min, uvs)
+        }
+        (None, None) => return None
     };
-    let (uv1, uv2, uv3, uv4) = (uv1/tss, uv2/tss, uv3/tss, uv4/tss);
-
-    TileMeshData {
-        size: Vec2::new(tiw, tih),
+    Some(TileMeshData {
+        size: tile_size,
         uv1,
         uv2,
         uv3,
-        uv4
+        uv4,
+        flip_h: false,
+        flip_v: false,
+        flip_d: false
+    })
+}
+
+// Computes the UVs of a tile in a tileset image, in the order bottom-left, bottom-right, top-right, top-left.
+// If flip_y is true, the tileset image is assumed to be stored upside-down, so rows are counted from the bottom of the image and the tile's content is flipped.
+fn tile_uvs(
+    tile_id: u32,
+    columns: u32,
+    tile_size: Vec2,
+    margin: f32,
+    spacing: f32,
+    image_size: Vec2,
+    flip_y: bool
+) -> [Vec2; 4] {
+    let (tiw, tih) = (tile_size.x, tile_size.y);                            // Tile width / height
+    let (tixi, tiyi) = (tile_id % columns, tile_id / columns);              // Tile x / y (ints)
+    let (tix, tiy) = (tixi as f32 * tiw, tiyi as f32 * tih);                // Tile x / y (floats)
+
+    // Creates UV coords in pixels, as if the image were not flipped
+    let tsm = Vec2::new(margin, margin);                        // Tileset margin
+    let tip = Vec2::new(tix, tiy) + Vec2::new(0.0, tih);        // Tile position
+    let tisp = Vec2::new(tixi as f32, tiyi as f32) * spacing;   // Tile spacing
+    let uv1 = tip + tsm + tisp;
+    let uv2 = uv1 + Vec2::new(tiw, 0.0);
+    let uv3 = uv1 + Vec2::new(tiw, -tih);
+    let uv4 = uv1 + Vec2::new(0.0, -tih);
+    let mut uvs = [uv1, uv2, uv3, uv4];
+
+    // Mirrors UVs vertically if the image is flipped, then normalizes them
+    for uv in &mut uvs {
+        if flip_y {
+            uv.y = image_size.y - uv.y;
+        }
+        *uv /= image_size;
     }
+    uvs
 }
 
 
@@ -292,44 +838,183 @@ impl<'map> MetaLayer<'map> {
         match self {
             Self::GeomColl(layer) => layer
                 .get_tile(x, y)
-                .map(|tile| MetaTile::GeomColl(tile.get_tile().unwrap())),
+                .map(|tile| MetaTile::GeomColl(tile.get_tile())),
             Self::Geom(layer) => layer
                 .get_tile(x, y)
-                .map(|tile| MetaTile::Geom(tile.get_tile().unwrap())),
+                .map(|tile| MetaTile::Geom(tile.get_tile())),
             Self::Coll(layer) => layer
                 .get_tile(x, y)
-                .map(|tile| MetaTile::Coll(tile.get_tile().unwrap())),
+                .map(|tile| MetaTile::Coll(tile.get_tile())),
         }
     }
 }
 
-// Tile from a `MetaLayer`
+// Tile from a `MetaLayer`.
+// None if the tile has no entry in its tileset, such as a tile without properties, which is a floor.
 enum MetaTile<'map> {
-    GeomColl(Tile<'map>),
-    Geom(Tile<'map>),
-    Coll(Tile<'map>)
+    GeomColl(Option<Tile<'map>>),
+    Geom(Option<Tile<'map>>),
+    Coll(Option<Tile<'map>>)
 }
 
 impl<'map> MetaTile<'map> {
 
     /// Geom tile type followed by coll tile type
-    fn get_types(&self) -> (TileType, TileType) {
-        match self {
+    fn get_types(&self, group_layer_name: &str, x: i32, y: i32) -> Result<(TileType, TileType), MapLoadError> {
+        let parse_type = |tile: &Option<Tile>| -> Result<TileType, MapLoadError> {
+            let t_type = tile
+                .as_ref()
+                .and_then(|tile| get_string_property(&tile.properties, "type"))
+                .unwrap_or("floor");
+            TileType::from_str(t_type).ok_or_else(|| MapLoadError::UnknownTileType {
+                layer: group_layer_name.to_owned(),
+                x,
+                y,
+                name: t_type.to_owned()
+            })
+        };
+        let types = match self {
             MetaTile::GeomColl(tile) => {
-                let t_type = get_string_property(&tile.properties, "type").unwrap_or("floor");
-                let t_type = TileType::from_str(t_type).unwrap();
+                let t_type = parse_type(tile)?;
                 (t_type, t_type)
             }
-            MetaTile::Geom(tile) => {
-                let t_type = get_string_property(&tile.properties, "type").unwrap_or("floor");
-                let t_type = TileType::from_str(t_type).unwrap();
-                (t_type, TileType::Floor)
-            }
-            MetaTile::Coll(tile) => {
-                let t_type = get_string_property(&tile.properties, "type").unwrap_or("floor");
-                let t_type = TileType::from_str(t_type).unwrap();
-                (TileType::Floor, t_type)
-            }
-        }
+            MetaTile::Geom(tile) => (parse_type(tile)?, TileType::Floor),
+            MetaTile::Coll(tile) => (TileType::Floor, parse_type(tile)?)
+        };
+        Ok(types)
     }
-}
\ No newline at end of file
+}
+
+
+#[test]
+fn test_tile_uvs() {
+    let tile_size = Vec2::new(16.0, 16.0);
+    let image_size = Vec2::new(64.0, 38.0);
+
+    // Second tile of second row, with a margin of 2 and spacing of 2
+    let [uv1, uv2, uv3, uv4] = tile_uvs(4, 3, tile_size, 2.0, 2.0, image_size, false);
+    assert_eq!(Vec2::new(20.0, 36.0) / image_size, uv1);
+    assert_eq!(Vec2::new(36.0, 36.0) / image_size, uv2);
+    assert_eq!(Vec2::new(36.0, 20.0) / image_size, uv3);
+    assert_eq!(Vec2::new(20.0, 20.0) / image_size, uv4);
+
+    // Same tile in an upside-down image
+    let [uv1, uv2, uv3, uv4] = tile_uvs(4, 3, tile_size, 2.0, 2.0, image_size, true);
+    assert_eq!(Vec2::new(20.0, 2.0) / image_size, uv1);
+    assert_eq!(Vec2::new(36.0, 2.0) / image_size, uv2);
+    assert_eq!(Vec2::new(36.0, 18.0) / image_size, uv3);
+    assert_eq!(Vec2::new(20.0, 18.0) / image_size, uv4);
+}
+
+#[test]
+fn test_meta_tile_without_properties() {
+    use tiled::{FilesystemResourceCache, Loader};
+
+    // Meta tileset whose tiles have no entries, so neither tile has a type
+    let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" orientation="orthogonal" renderorder="right-down" width="1" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="3" nextobjectid="1">
+ <tileset firstgid="1" name="meta" tilewidth="16" tileheight="16" tilecount="2" columns="0">
+ </tileset>
+ <group id="1" name="ground">
+  <layer id="2" name="geom_coll" width="1" height="2">
+   <properties>
+    <property name="type" value="geom_coll"/>
+   </properties>
+   <data encoding="csv">
+1,
+2
+   </data>
+  </layer>
+ </group>
+</map>
+"#;
+    let mut loader = Loader::with_cache(FilesystemResourceCache::new());
+    let tiled_map = loader.load_tmx_map_from(tmx.as_bytes(), std::path::Path::new("untyped.tmx")).unwrap();
+    let mut current_map = CurrentMap::new("untyped.tmx", Handle::default());
+    let mut current_map_graphics = CurrentMapGraphics::default();
+    process_tiled_map(&tiled_map, false, &mut current_map, &mut current_map_graphics).unwrap();
+
+    // Both tiles are floors
+    assert_eq!(Some(&TileType::Floor), current_map.meta_tiles.get(&IVec2::new(0, 0)));
+    assert_eq!(Some(&TileType::Floor), current_map.meta_tiles.get(&IVec2::new(0, 1)));
+    assert!(check_tiled_map(&tiled_map, false).is_empty());
+}
+
+#[test]
+fn test_root_object_above_wall() {
+    use tiled::{FilesystemResourceCache, Loader};
+
+    // Single column with a wall in the middle, and a root object layer placing an object on the top tile
+    let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" orientation="orthogonal" renderorder="right-down" width="1" height="3" tilewidth="16" tileheight="16" infinite="0" nextlayerid="4" nextobjectid="2">
+ <tileset firstgid="1" name="meta" tilewidth="16" tileheight="16" tilecount="2" columns="0">
+  <tile id="1">
+   <properties>
+    <property name="type" value="wall"/>
+   </properties>
+  </tile>
+ </tileset>
+ <objectgroup id="1" name="objects">
+  <object id="1" name="spawn" x="8" y="8">
+   <point/>
+  </object>
+ </objectgroup>
+ <group id="2" name="ground">
+  <layer id="3" name="geom_coll" width="1" height="3">
+   <properties>
+    <property name="type" value="geom_coll"/>
+   </properties>
+   <data encoding="csv">
+0,
+2,
+0
+   </data>
+  </layer>
+ </group>
+</map>
+"#;
+    let mut loader = Loader::with_cache(FilesystemResourceCache::new());
+    let tiled_map = loader.load_tmx_map_from(tmx.as_bytes(), std::path::Path::new("wall.tmx")).unwrap();
+    let mut current_map = CurrentMap::new("wall.tmx", Handle::default());
+    let mut current_map_graphics = CurrentMapGraphics::default();
+    process_tiled_map(&tiled_map, false, &mut current_map, &mut current_map_graphics).unwrap();
+
+    // Object sits on top of the wall, a tile up, rather than on the ground the wall rises from
+    let object = &current_map.objects[0];
+    assert_eq!("spawn", object.name);
+    assert_eq!(Vec3::new(8.0, 16.0, -24.0), object.position);
+}
+
+#[test]
+fn test_bridge_ending_in_lip() {
+    use crate::map::{MapBuilder, MapConfig};
+
+    // Single column of a group raised a tile up, with a wall topped by a lip
+    let lip_map = |bridge: bool| {
+        let mut builder = MapBuilder::new("lip.tmx", UVec2::new(1, 3), UVec2::new(16, 16));
+        let group = builder.add_group("raised", 1, bridge);
+        builder.set_tile_type(group, IVec2::new(0, 2), TileType::Wall);
+        builder.set_tile_type(group, IVec2::new(0, 1), TileType::LipN);
+        let (current_map, _) = builder.build(&MapConfig::default()).unwrap();
+        current_map
+    };
+    let piece_at = |current_map: &CurrentMap, x, y, z| current_map.terrain
+        .get(Coords::new(x, y, z))
+        .copied()
+        .unwrap_or(TerrainPiece::Empty);
+
+    // Both write the wall and the lip on top of it
+    for bridge in [false, true] {
+        let current_map = lip_map(bridge);
+        assert_eq!(TerrainPiece::Cuboid, piece_at(&current_map, 0, 1, 1));
+        assert_eq!(TerrainPiece::Lip, piece_at(&current_map, 0, 2, 1));
+    }
+
+    // Only the group that isn't a bridge fills the space beneath the cliff
+    let filled = lip_map(false);
+    assert_eq!(TerrainPiece::Cuboid, piece_at(&filled, 0, 0, 1));
+    assert_eq!(TerrainPiece::Cuboid, piece_at(&filled, 0, 0, 0));
+    let bridged = lip_map(true);
+    assert_eq!(TerrainPiece::Empty, piece_at(&bridged, 0, 0, 1));
+    assert_eq!(TerrainPiece::Empty, piece_at(&bridged, 0, 0, 0));
+}
@@ -1,17 +1,32 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 use bevy::reflect::TypeUuid;
-use bevy::asset::{AssetLoader, AssetServerSettings, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::asset::{AssetLoader, AssetPath, AssetServerSettings, BoxedFuture, LoadContext, LoadedAsset};
 use bevy::prelude::*;
 use tiled::{Map, FilesystemResourceCache, Loader};
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::extensions::PathExt;
+use crate::map::{MapLoadError, MapLoadErrors};
 
 #[derive(Debug, TypeUuid)]
 #[uuid = "24740238-86b8-11ec-a8a3-0242ac120002"]
 pub struct VidyaMap {
-    pub tiled_map: Map
+    /// Shared so that the map can be constructed off the main thread
+    pub tiled_map: Arc<Map>,
+    /// External tilesets the map depends on
+    pub tilesets: Vec<Handle<VidyaTileset>>
 }
 
+/// External tileset (TSX file) that a [`VidyaMap`] depends on.
+/// The tileset itself is read by the map's loader. This asset only exists so that changes to the file are noticed when hot reloading.
+#[derive(Debug, TypeUuid)]
+#[uuid = "6c1f8e1a-3f0b-4a5e-9a53-0d7f3e1c2b84"]
+pub struct VidyaTileset;
+
 pub struct VidyaMapLoader {
-    assets_folder: PathBuf
+    assets_folder: PathBuf,
+    errors: MapLoadErrors
 }
 
 impl FromWorld for VidyaMapLoader {
@@ -20,8 +35,10 @@ impl FromWorld for VidyaMapLoader {
             .get_resource::<AssetServerSettings>()
             .unwrap()
             .asset_folder;
+        let assets_folder = PathBuf::from(asset_folder);
         Self {
-            assets_folder: PathBuf::from(asset_folder)
+            assets_folder,
+            errors: world.get_resource_or_insert_with(MapLoadErrors::default).clone()
         }
     }
 }
@@ -37,8 +54,24 @@ impl AssetLoader for VidyaMapLoader {
             path.push(&self.assets_folder);
             path.push(load_context.path());
             let mut loader = Loader::with_cache(FilesystemResourceCache::new());
-            let tiled_map = loader.load_tmx_map_from(bytes, &path).unwrap();
-            load_context.set_default_asset(LoadedAsset::new(VidyaMap { tiled_map }));
+            let result = loader
+                .load_tmx_map_from(bytes, &path)
+                .map_err(MapLoadError::from);
+            let tiled_map = self.errors.record(load_context.path(), result)?;
+
+            // Depends on external tilesets so that they get watched for changes
+            let map_folder = load_context.path().parent().map(PathBuf::from).unwrap_or_default();
+            let tileset_paths: Vec<AssetPath<'static>> = external_tileset_sources(bytes)
+                .into_iter()
+                .map(|source| AssetPath::new(map_folder.join(source).normalize(), None))
+                .collect();
+            let tilesets = tileset_paths
+                .iter()
+                .map(|path| load_context.get_handle(path.clone()))
+                .collect();
+            load_context.set_default_asset(
+                LoadedAsset::new(VidyaMap { tiled_map: Arc::new(tiled_map), tilesets }).with_dependencies(tileset_paths)
+            );
             Ok(())
         })
     }
@@ -46,4 +79,79 @@ impl AssetLoader for VidyaMapLoader {
     fn extensions(&self) -> &[&str] {
         &["tmx"]
     }
-}
\ No newline at end of file
+}
+
+#[derive(Default)]
+pub struct VidyaTilesetLoader;
+
+impl AssetLoader for VidyaTilesetLoader {
+    fn load<'a>(
+        &'a self,
+        _bytes: &'a [u8],
+        load_context: &'a mut LoadContext
+    ) -> BoxedFuture<'a, anyhow::Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            load_context.set_default_asset(LoadedAsset::new(VidyaTileset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tsx"]
+    }
+}
+
+// Sources of the external tilesets referenced by a TMX file, relative to the TMX file.
+// Only tilesets directly within the map element count. Comments and CDATA are skipped by the XML reader.
+fn external_tileset_sources(tmx: &[u8]) -> Vec<String> {
+    let mut sources = Vec::new();
+    let mut depth = 0;
+    for event in EventReader::new(tmx) {
+        match event {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
+                depth += 1;
+                if depth == 2 && name.local_name == "tileset" {
+                    let source = attributes
+                        .into_iter()
+                        .find(|attribute| attribute.name.local_name == "source")
+                        .map(|attribute| attribute.value);
+                    sources.extend(source);
+                }
+            }
+            Ok(XmlEvent::EndElement { .. }) => depth -= 1,
+            Ok(_) => {}
+            Err(error) => {
+                log::warn!("Failed to read tileset sources of map: {}", error);
+                break;
+            }
+        }
+    }
+    sources
+}
+
+
+#[test]
+fn test_external_tileset_sources() {
+    let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" orientation="orthogonal" width="2" height="2" tilewidth="16" tileheight="16">
+ <tileset firstgid="1" source="../tsx/terrain.tsx"/>
+ <tileset firstgid="673" name="embedded" tilewidth="16" tileheight="16" tilecount="1" columns="1">
+  <image source="../images/embedded.png" width="16" height="16"/>
+ </tileset>
+ <tileset firstgid="674" source="collision.tsx"/>
+</map>"#;
+    assert_eq!(vec!["../tsx/terrain.tsx", "collision.tsx"], external_tileset_sources(tmx.as_bytes()));
+
+    // Single quotes and whitespace around attributes are valid XML, and tilesets in comments or CDATA aren't tilesets
+    let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" orientation="orthogonal" width="2" height="2" tilewidth="16" tileheight="16">
+ <!-- <tileset firstgid="1" source="commented.tsx"/> -->
+ <tileset firstgid='1' source='single.tsx'/>
+ <tileset firstgid="2"
+          source = "spaced.tsx" />
+ <properties>
+  <property name="notes"><![CDATA[<tileset firstgid="3" source="cdata.tsx"/>]]></property>
+ </properties>
+</map>"#;
+    assert_eq!(vec!["single.tsx", "spaced.tsx"], external_tileset_sources(tmx.as_bytes()));
+}
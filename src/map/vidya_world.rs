@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use bevy::asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+use tiled::Map;
+
+use crate::extensions::PathExt;
+use crate::map::{VidyaMap, MapPlacement, MapLoadError, MapLoadErrors, map_tile_bounds};
+
+/// Many maps laid out next to each other, read from a Tiled world (".world") file.
+/// Maps of a world are spawned together, and share one terrain.
+#[derive(Debug, TypeUuid)]
+#[uuid = "a3d2c1f4-6b7e-4f0a-8c5d-2e9b7f1a4c36"]
+pub struct VidyaWorld {
+    /// Maps of the world, in the order they appear in the world file
+    pub maps: Vec<WorldMap>
+}
+
+/// Map placed in a [`VidyaWorld`]
+#[derive(Debug, Clone)]
+pub struct WorldMap {
+    /// Position of the map's top-left corner within the world, in pixels
+    pub offset: IVec2,
+    pub map_handle: Handle<VidyaMap>
+}
+
+impl VidyaWorld {
+
+    /// Maps of the world, along with where they are placed.
+    /// None until every map has loaded.
+    pub fn placed_maps(&self, vidya_maps: &Assets<VidyaMap>) -> Option<Vec<(Arc<Map>, MapPlacement)>> {
+        let tiled_maps = self.maps
+            .iter()
+            .map(|world_map| Some(vidya_maps.get(&world_map.map_handle)?.tiled_map.clone()))
+            .collect::<Option<Vec<Arc<Map>>>>()?;
+
+        // Offsets are converted to tiles, and every map's bottom row is measured against the lowest row of the world
+        let tile_offsets: Vec<IVec2> = self.maps
+            .iter()
+            .zip(&tiled_maps)
+            .map(|(world_map, tiled_map)| {
+                let tile_size = IVec2::new(tiled_map.tile_width as i32, tiled_map.tile_height as i32);
+                IVec2::new(world_map.offset.x.div_euclid(tile_size.x), world_map.offset.y.div_euclid(tile_size.y))
+            })
+            .collect();
+        let bottom_row = tiled_maps
+            .iter()
+            .zip(&tile_offsets)
+            .map(|(tiled_map, tile_offset)| map_tile_bounds(tiled_map).max.y + tile_offset.y - 1)
+            .max()
+            .unwrap_or(0);
+
+        // Tilesets of every map are listed one after another
+        let mut tileset_offset = 0;
+        let placed_maps = tiled_maps
+            .into_iter()
+            .zip(tile_offsets)
+            .map(|(tiled_map, tile_offset)| {
+                let placement = MapPlacement { tile_offset, bottom_row, tileset_offset };
+                tileset_offset += tiled_map.tilesets().len();
+                (tiled_map, placement)
+            })
+            .collect();
+        Some(placed_maps)
+    }
+}
+
+/// Loads [`VidyaWorld`]s, along with every map in them
+pub struct VidyaWorldLoader {
+    errors: MapLoadErrors
+}
+
+impl FromWorld for VidyaWorldLoader {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            errors: world.get_resource_or_insert_with(MapLoadErrors::default).clone()
+        }
+    }
+}
+
+impl AssetLoader for VidyaWorldLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext
+    ) -> BoxedFuture<'a, anyhow::Result<(), anyhow::Error>> {
+        Box::pin(async move {
+
+            // Map paths are relative to the world file
+            let world_folder = load_context.path().parent().map(PathBuf::from).unwrap_or_default();
+            let map_paths: Vec<(AssetPath<'static>, IVec2)> = self.errors.record(load_context.path(), parse_world(bytes))?
+                .into_iter()
+                .map(|(file_name, offset)| (AssetPath::new(world_folder.join(file_name).normalize(), None), offset))
+                .collect();
+            let maps = map_paths
+                .iter()
+                .map(|(path, offset)| WorldMap {
+                    offset: *offset,
+                    map_handle: load_context.get_handle(path.clone())
+                })
+                .collect();
+            let dependencies = map_paths.into_iter().map(|(path, _)| path).collect();
+            load_context.set_default_asset(LoadedAsset::new(VidyaWorld { maps }).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["world"]
+    }
+}
+
+#[derive(Deserialize)]
+struct WorldFile {
+    #[serde(default)]
+    maps: Vec<WorldFileMap>,
+    #[serde(default)]
+    patterns: Vec<serde_json::Value>
+}
+
+#[derive(Deserialize)]
+struct WorldFileMap {
+    #[serde(rename = "fileName")]
+    file_name: String,
+    x: i32,
+    y: i32
+}
+
+// File names and pixel offsets of the maps in a world file.
+// Maps matched by patterns are not supported, as the world file doesn't say which files exist.
+fn parse_world(bytes: &[u8]) -> Result<Vec<(String, IVec2)>, MapLoadError> {
+    let world: WorldFile = serde_json::from_slice(bytes).map_err(|err| MapLoadError::World(err.to_string()))?;
+    if !world.patterns.is_empty() {
+        log::warn!("Ignoring {} pattern(s) in world file", world.patterns.len());
+    }
+    Ok(world.maps
+        .into_iter()
+        .map(|map| (map.file_name, IVec2::new(map.x, map.y)))
+        .collect())
+}
+
+
+#[test]
+fn test_parse_world() {
+    let world = br#"{
+    "maps": [
+        { "fileName": "overworld_a.tmx", "height": 640, "width": 640, "x": 0, "y": 0 },
+        { "fileName": "overworld_b.tmx", "height": 640, "width": 320, "x": 640, "y": -320 }
+    ],
+    "onlyShowAdjacentMaps": false,
+    "type": "world"
+}"#;
+    assert_eq!(
+        vec![
+            ("overworld_a.tmx".to_owned(), IVec2::new(0, 0)),
+            ("overworld_b.tmx".to_owned(), IVec2::new(640, -320))
+        ],
+        parse_world(world).unwrap()
+    );
+    assert!(parse_world(b"<map/>").is_err());
+}
@@ -1,16 +1,21 @@
 mod cuboid;
 mod slope;
+mod step;
 
 use std::fmt::Debug;
 
 use bevy::{prelude::*, math::Vec3Swizzles };
 
-use crate::physics::{ Terrain, Coords, TerrainPiece, TerrainPieceRef };
+use crate::physics::{ Terrain, Coords, TerrainPiece, TerrainPieceRef, LIP_HEIGHT, STEP_HEIGHT };
 use cuboid::collide_cuboid_with_cylinder;
-use slope::collide_slope_with_cylinder;
+use slope::{ collide_slope_with_cylinder, collide_slope_e_with_cylinder, collide_slope_w_with_cylinder };
+use step::{ collide_step_with_cylinder, collide_stairs_with_cylinder };
 
 const T_EPSILON: f32 = 0.0001;
 
+// Amount a collision's t value is pulled back by when it is applied
+const T_BACKOFF: f32 = 0.01;
+
 /// Represents a collision event
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Collision {
@@ -28,6 +33,19 @@ impl Collision {
     fn new(t: f32, velocity: Vec3, typ: CollisionType) -> Self {
         Self { t, velocity, offset: Vec3::ZERO, typ }
     }
+
+    /// T value the collider is moved to when the collision is applied, which stops it just short of what it hit
+    pub fn applied_t(&self) -> f32 {
+        (self.t - T_BACKOFF).min(1.0).max(0.0)
+    }
+
+    /// Applies the collision to a collider that was moving by delta.
+    /// Returns the collider's new center, followed by what remains of its movement.
+    /// The remaining movement is the velocity and offset of the collision, scaled down to the part of the movement that is left.
+    pub fn apply(&self, center: Vec3, delta: Vec3) -> (Vec3, Vec3) {
+        let t = self.applied_t();
+        (center + delta * t, (self.velocity + self.offset) * (1.0 - t))
+    }
 }
 
 
@@ -92,10 +110,27 @@ impl PieceCollider {
         match self.piece {
             TerrainPiece::Cuboid => collide_cuboid_with_cylinder(self.aabb(), cyl, delta),
             TerrainPiece::Slope => collide_slope_with_cylinder(self.aabb(), cyl, delta),
+            TerrainPiece::SlopeE => collide_slope_e_with_cylinder(self.aabb(), cyl, delta),
+            TerrainPiece::SlopeW => collide_slope_w_with_cylinder(self.aabb(), cyl, delta),
+            TerrainPiece::HalfCuboid => collide_step_with_cylinder(self.half_aabb(), cyl, delta),
+            TerrainPiece::Stairs => collide_stairs_with_cylinder(self.aabb(), cyl, delta),
+            TerrainPiece::Lip => collide_cuboid_with_cylinder(self.lip_aabb(), cyl, delta),
             _ => None
         }
     }
 
+    // Aabb of a half-height piece, which is at the bottom of the piece
+    fn half_aabb(&self) -> Aabb {
+        let size = self.size * Vec3::new(1.0, STEP_HEIGHT, 1.0);
+        Aabb { min: self.position, max: self.position + size }
+    }
+
+    // Aabb of a lip, which is at the bottom/far corner of the piece
+    fn lip_aabb(&self) -> Aabb {
+        let size = self.size * Vec3::new(1.0, LIP_HEIGHT, LIP_HEIGHT);
+        Aabb { min: self.position, max: self.position + size }
+    }
+
 
     fn aabb(&self) -> Aabb {
         Aabb { min: self.position, max: self.position + self.size }
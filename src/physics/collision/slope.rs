@@ -223,6 +223,56 @@ pub fn collide_slope_with_cylinder(ter_bounds: Aabb, cyl: &CylinderCollider, del
     None
 }
 
+/// Collides a ramp that descends to the east with a cylinder.
+/// Swapping the x and z axes turns it into a ramp that rises to the north, which is what [`collide_slope_with_cylinder`] handles.
+pub fn collide_slope_e_with_cylinder(ter_bounds: Aabb, cyl: &CylinderCollider, delta: Vec3) -> Option<Collision> {
+    collide_rotated_slope_with_cylinder(
+        ter_bounds,
+        cyl,
+        delta,
+        |v| v.zyx(),
+        |v| v.zyx()
+    )
+}
+
+/// Collides a ramp that descends to the west with a cylinder.
+/// Like [`collide_slope_e_with_cylinder`], but the x axis is also mirrored.
+pub fn collide_slope_w_with_cylinder(ter_bounds: Aabb, cyl: &CylinderCollider, delta: Vec3) -> Option<Collision> {
+    collide_rotated_slope_with_cylinder(
+        ter_bounds,
+        cyl,
+        delta,
+        |v| Vec3::new(v.z, v.y, -v.x),
+        |v| Vec3::new(-v.z, v.y, v.x)
+    )
+}
+
+// Moves the slope and cylinder into a space where the slope rises to the north, collides them, then moves the result back
+fn collide_rotated_slope_with_cylinder(
+    ter_bounds: Aabb,
+    cyl: &CylinderCollider,
+    delta: Vec3,
+    to_local: impl Fn(Vec3) -> Vec3,
+    to_world: impl Fn(Vec3) -> Vec3
+) -> Option<Collision> {
+    let corner_a = to_local(ter_bounds.min);
+    let corner_b = to_local(ter_bounds.max);
+    let local_bounds = Aabb {
+        min: corner_a.min(corner_b),
+        max: corner_a.max(corner_b)
+    };
+    let local_cyl = CylinderCollider {
+        center: to_local(cyl.center),
+        ..*cyl
+    };
+    let coll = collide_slope_with_cylinder(local_bounds, &local_cyl, to_local(delta))?;
+    Some(Collision {
+        velocity: to_world(coll.velocity),
+        offset: to_world(coll.offset),
+        ..coll
+    })
+}
+
 fn slope_intercept_of(a: Vec2, b: Vec2) -> (f32, f32) {
     let diff = b - a;
     let slope = diff.y / diff.x;
@@ -320,3 +370,81 @@ fn collide2d(a1: Vec2, b1: Vec2, mut a2: Vec2, mut b2: Vec2, normal: Vec2) -> Op
     let offset = Vec2::new(0.0, final_y - inter_y) + normal*Vec2::new(0.01, 0.01);
     Some((collision, offset))
 }
+
+
+#[test]
+fn test_slope_directions_with_cylinder() {
+    let bounds = Aabb {
+        min: Vec3::ZERO,
+        max: Vec3::new(16.0, 16.0, 16.0)
+    };
+    let (radius, half_height) = (4.0, 8.0);
+    let center = Vec2::new(8.0, 8.0);
+
+    // Collide function, height of the surface at an x/z position, uphill direction and direction to approach the side from
+    type Collide = fn(Aabb, &CylinderCollider, Vec3) -> Option<Collision>;
+    let slopes: [(&str, Collide, fn(Vec2) -> f32, Vec2, Vec2); 3] = [
+        ("north", collide_slope_with_cylinder, |p| 16.0 - p.y, Vec2::new(0.0, -1.0), Vec2::new(1.0, 0.0)),
+        ("east", collide_slope_e_with_cylinder, |p| 16.0 - p.x, Vec2::new(-1.0, 0.0), Vec2::new(0.0, 1.0)),
+        ("west", collide_slope_w_with_cylinder, |p| p.x, Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0))
+    ];
+
+    // Where the cylinder ends up once the physics retry loop applies the collision.
+    // Collisions carry no normal, so the direction the cylinder gets pushed away from its unobstructed path stands in for it.
+    let resolve = |collide: Collide, cyl: CylinderCollider, delta: Vec3| -> (CollisionType, Vec3, Vec3) {
+        let coll = collide(bounds, &cyl, delta).unwrap();
+        let (moved, remaining) = coll.apply(cyl.center, delta);
+        let end = moved + remaining;
+        (coll.typ, end, (end - (cyl.center + delta)).normalize_or_zero())
+    };
+    let cylinder = |xz: Vec2, bottom: f32| CylinderCollider {
+        center: Vec3::new(xz.x, bottom + half_height, xz.y),
+        radius,
+        half_height
+    };
+    let to_3d = |v: Vec2| Vec3::new(v.x, 0.0, v.y);
+
+    let mut ends = Vec::new();
+    for (name, collide, surface, uphill, side) in slopes {
+
+        // Walks up the slope from its middle, starting just above it
+        let cyl = cylinder(center - uphill * radius, surface(center) + 0.05);
+        let (typ, end, push) = resolve(collide, cyl, to_3d(uphill * 2.0));
+        let lead = end.xz() + uphill * radius;
+        let clearance = end.y - half_height - surface(lead);
+        assert_eq!(CollisionType::Floor, typ, "{}", name);
+        assert!(clearance >= 0.0 && clearance < 0.1, "{} ended {} above the slope", name, clearance);
+        assert!((end.xz() - cyl.center.xz()).dot(uphill) > 1.99, "{}", name);
+        assert!(push.abs_diff_eq(Vec3::Y, 0.001), "{} pushed towards {}", name, push);
+        let walk_up_end = end;
+
+        // Walks into the low edge, just above the ground in front of it
+        let cyl = cylinder(center - uphill * (8.5 + radius), 0.25);
+        let (typ, end, push) = resolve(collide, cyl, to_3d(uphill * 2.0));
+        let lead = end.xz() + uphill * radius;
+        let clearance = end.y - half_height - surface(lead);
+        assert_eq!(CollisionType::Floor, typ, "{}", name);
+        assert!(clearance >= 0.0 && clearance < 0.1, "{} ended {} above the slope", name, clearance);
+        assert!(push.abs_diff_eq(Vec3::Y, 0.001), "{} pushed towards {}", name, push);
+
+        // Walks into the side, beneath the slope's surface, and is stopped at the side
+        let cyl = cylinder(center - side * (8.0 + radius + 1.0) - uphill * 4.0, 0.0);
+        let (typ, end, push) = resolve(collide, cyl, to_3d(side * 2.0));
+        let reach = (end.xz() - center).dot(side) + radius;
+        assert_eq!(CollisionType::Wall, typ, "{}", name);
+        assert!(reach <= -8.0 && reach > -8.1, "{} reached {}", name, reach);
+        assert_eq!(cyl.center.y, end.y, "{}", name);
+        assert!((end.xz() - cyl.center.xz()).dot(uphill).abs() < 0.001, "{}", name);
+        assert!(push.abs_diff_eq(-to_3d(side), 0.001), "{} pushed towards {}", name, push);
+        ends.push((walk_up_end, end));
+    }
+
+    // East and west slopes behave exactly like the north slope, turned to face their direction
+    let (north, east, west) = (ends[0], ends[1], ends[2]);
+    let turn_east = |v: Vec3| v.zyx();
+    let turn_west = |v: Vec3| Vec3::new(16.0 - v.z, v.y, v.x);
+    assert!(east.0.abs_diff_eq(turn_east(north.0), 0.001));
+    assert!(east.1.abs_diff_eq(turn_east(north.1), 0.001));
+    assert!(west.0.abs_diff_eq(turn_west(north.0), 0.001));
+    assert!(west.1.abs_diff_eq(turn_west(north.1), 0.001));
+}
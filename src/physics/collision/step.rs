@@ -0,0 +1,104 @@
+use bevy::math::Vec3;
+
+use super::{ Aabb, CylinderCollider, Collision, CollisionType, T_EPSILON };
+use super::cuboid::collide_cuboid_with_cylinder;
+
+/// Collides a cuboid that is short enough to step onto with a cylinder.
+/// A cylinder that walks into its side from its base is lifted on top of it instead of being stopped.
+pub fn collide_step_with_cylinder(ter_bounds: Aabb, cyl: &CylinderCollider, delta: Vec3) -> Option<Collision> {
+
+    const BASE_EPSILON: f32 = 0.1;
+    const LIFT_EPSILON: f32 = 0.01;
+
+    // Collides with the step as if it were a regular cuboid
+    let coll = collide_cuboid_with_cylinder(ter_bounds, cyl, delta)?;
+    if coll.typ != CollisionType::Wall {
+        return Some(coll);
+    }
+
+    // Only steps up when standing at the base of the step
+    let t = coll.applied_t();
+    let bottom = cyl.center.y + delta.y * t - cyl.half_height;
+    if bottom + BASE_EPSILON < ter_bounds.min.y {
+        return Some(coll);
+    }
+
+    // Offsets get scaled down to what remains of the movement once the collision is applied, so the lift is scaled up to compensate
+    let lift = ter_bounds.max.y - bottom + LIFT_EPSILON;
+    let remaining = (1.0 - t).max(T_EPSILON);
+    Some(Collision {
+        t: coll.t,
+        velocity: Vec3::new(delta.x, 0.0, delta.z),
+        offset: Vec3::new(0.0, lift / remaining, 0.0),
+        typ: CollisionType::Floor
+    })
+}
+
+/// Collides stairs that rise to the north with a cylinder.
+/// Each of the two steps is its own cuboid that can be stepped onto.
+pub fn collide_stairs_with_cylinder(ter_bounds: Aabb, cyl: &CylinderCollider, delta: Vec3) -> Option<Collision> {
+    let (min, max) = (ter_bounds.min, ter_bounds.max);
+    let mid = ter_bounds.center();
+    let lower = Aabb {
+        min,
+        max: Vec3::new(max.x, mid.y, max.z)
+    };
+    let upper = Aabb {
+        min: Vec3::new(min.x, mid.y, min.z),
+        max: Vec3::new(max.x, max.y, mid.z)
+    };
+    let lower_coll = collide_step_with_cylinder(lower, cyl, delta);
+    let upper_coll = collide_step_with_cylinder(upper, cyl, delta);
+    match (lower_coll, upper_coll) {
+        (Some(lower_coll), Some(upper_coll)) if upper_coll.t < lower_coll.t => Some(upper_coll),
+        (lower_coll, upper_coll) => lower_coll.or(upper_coll)
+    }
+}
+
+
+#[test]
+fn test_step_lifts_to_top() {
+    let step = Aabb {
+        min: Vec3::ZERO,
+        max: Vec3::new(16.0, 8.0, 16.0)
+    };
+
+    // Walks east into the side of the step, colliding close to the start and close to the end of the movement
+    for t in [0.001, 0.5, 0.999] {
+        let delta = Vec3::new(2.0, 0.0, 0.0);
+        let cyl = CylinderCollider {
+            center: Vec3::new(-4.0 - delta.x * t, 8.0, 8.0),
+            radius: 4.0,
+            half_height: 8.0
+        };
+        let coll = collide_step_with_cylinder(step, &cyl, delta).unwrap();
+        assert_eq!(CollisionType::Floor, coll.typ);
+        let (center, remaining) = coll.apply(cyl.center, delta);
+        let end = center + remaining;
+        assert!((end.y - cyl.half_height - step.max.y).abs() < 0.02, "t = {}, ended at {}", t, end);
+        assert!((end.x - (cyl.center.x + delta.x)).abs() < 0.001);
+    }
+}
+
+#[test]
+fn test_stairs_lift_to_lower_step() {
+    let stairs = Aabb {
+        min: Vec3::ZERO,
+        max: Vec3::new(16.0, 16.0, 16.0)
+    };
+
+    // Walks north into the lower step, which is at the south end of the stairs
+    for t in [0.001, 0.5, 0.999] {
+        let delta = Vec3::new(0.0, 0.0, -2.0);
+        let cyl = CylinderCollider {
+            center: Vec3::new(8.0, 8.0, 20.0 - delta.z * t),
+            radius: 4.0,
+            half_height: 8.0
+        };
+        let coll = collide_stairs_with_cylinder(stairs, &cyl, delta).unwrap();
+        assert_eq!(CollisionType::Floor, coll.typ);
+        let (center, remaining) = coll.apply(cyl.center, delta);
+        let end = center + remaining;
+        assert!((end.y - cyl.half_height - 8.0).abs() < 0.02, "t = {}, ended at {}", t, end);
+    }
+}
@@ -112,7 +112,7 @@ impl Default for Gravity {
 #[derive(Clone, Debug, Component, PartialEq)]
 pub struct Caster {
     /// Distance to be casted downward.
-    /// Should be positive.
+    /// Should be positive. Casts at least as far as a [`crate::physics::STEP_HEIGHT`] step is tall, regardless.
     pub distance: f32
 }
 
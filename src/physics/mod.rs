@@ -137,10 +137,12 @@ fn cast_cylinders_on_terrain(
             radius: shape.radius,
             half_height: shape.half_height
         };
+        // Casts at least as far as a step is tall, so that walking down stairs doesn't count as falling
+        let distance = caster.distance.max(terrain.piece_size().y * STEP_HEIGHT);
         let coll_info = coll_cyl_with_retries(
             &terrain,
             &mut cylinder,
-            Vec3::new(0.0, -caster.distance, 0.0),
+            Vec3::new(0.0, -distance, 0.0),
             COLLISION_RETRIES
         );
         if let Some(coll_info) = coll_info {
@@ -157,8 +159,7 @@ fn coll_cyl_with_retries(
     mut delta: Vec3,
     retries: usize
 ) -> Option<CollisionInfo> {
-    
-    const EPSILON: f32 = 0.01;
+
     if retries == 0 {
         panic!("Invalid number of retries {}", retries);
     }
@@ -180,12 +181,10 @@ fn coll_cyl_with_retries(
                     break;
                 }
                 
-                // Massages t value
-                let t = (collision.t - EPSILON).min(1.0).max(0.0);
-
                 // Applies collision to previous position
-                cyl.center += delta * t;
-                delta = (collision.velocity + collision.offset) * (1.0 - t);
+                let (center, remaining) = collision.apply(cyl.center, delta);
+                cyl.center = center;
+                delta = remaining;
                 if collision.typ == CollisionType::Floor {
                     on_ground = true;
                 }
@@ -1,11 +1,12 @@
 use bevy::prelude::*;
 use bevy::{utils::HashMap};
 use bevy::math::{Vec3, UVec3};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 use super::Aabb;
 
 /// All of the terrain in a [`World`] at a given time as a resource.
-#[derive(Component, Clone)]
+#[derive(Component, Clone, PartialEq)]
 pub struct Terrain {
     chunks: HashMap<ChunkCoords, Chunk>,
     piece_size: Vec3,
@@ -61,6 +62,33 @@ impl Terrain {
     }
 
 
+    /// Iterates over every chunk that exists, along with its pieces
+    pub fn chunks(&self) -> impl Iterator<Item=(ChunkCoords, &[TerrainPiece])> + '_ {
+        self.chunks
+            .iter()
+            .map(|(coords, chunk)| (*coords, chunk.0.as_slice()))
+    }
+
+    /// Replaces the chunk at the specified coordinates.
+    /// Pieces are ordered by x, then y, then z. Panics if there isn't exactly one piece for every position in the chunk.
+    pub fn insert_chunk(&mut self, coords: ChunkCoords, pieces: Vec<TerrainPiece>) {
+        let chunk_size = self.chunk_size.x * self.chunk_size.y * self.chunk_size.z;
+        if pieces.len() != chunk_size as usize {
+            panic!("Invalid number of pieces in chunk");
+        }
+        self.chunks.insert(coords, Chunk(pieces));
+    }
+
+    /// Removes the chunk at the specified coordinates, returning its pieces if it existed
+    pub fn remove_chunk(&mut self, coords: ChunkCoords) -> Option<Vec<TerrainPiece>> {
+        self.chunks.remove(&coords).map(|chunk| chunk.0)
+    }
+
+    /// True if the chunk at the specified coordinates exists
+    pub fn contains_chunk(&self, coords: ChunkCoords) -> bool {
+        self.chunks.contains_key(&coords)
+    }
+
     /// Iterates over chunks within a range.
     /// Min is inclusive, and max is exclusive.
     pub fn iter_chunks<'terrain>(
@@ -149,12 +177,51 @@ impl Terrain {
     }
 }
 
+/// Height of a [`TerrainPiece::Lip`], as a fraction of the height of a piece
+pub const LIP_HEIGHT: f32 = 0.25;
+
+/// Height of a stair step or half-height ledge, as a fraction of the height of a piece
+pub const STEP_HEIGHT: f32 = 0.5;
+
 /// One piece of terrain
-#[derive(Debug,Copy, Clone, Eq, PartialEq)]
+#[derive(Debug,Copy, Clone, Eq, PartialEq, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
 pub enum TerrainPiece {
     Empty,
     Cuboid,
-    Slope
+    /// Ramp that rises to the north
+    Slope,
+    /// Ramp that descends to the east
+    SlopeE,
+    /// Ramp that descends to the west
+    SlopeW,
+    /// Bottom [`STEP_HEIGHT`] of the piece. Can be walked onto without jumping.
+    HalfCuboid,
+    /// Two steps that rise to the north, each half as tall and deep as the piece
+    Stairs,
+    /// Thin rim along the far edge of a cliff top. Sits at the bottom of the piece, and is [`LIP_HEIGHT`] as tall and deep as the piece.
+    Lip
+}
+
+impl TerrainPiece {
+
+    /// Height of the top of the piece at a point, as a fraction of the height of the piece.
+    /// The point is the position within the piece on the x/z plane, where (0, 0) is the north-west corner and (1, 1) is the south-east corner.
+    /// None if the piece has no top at that point.
+    pub fn surface_height(&self, point: Vec2) -> Option<f32> {
+        match self {
+            Self::Empty => None,
+            Self::Cuboid => Some(1.0),
+            Self::Slope => Some(1.0 - point.y),
+            Self::SlopeE => Some(1.0 - point.x),
+            Self::SlopeW => Some(point.x),
+            Self::HalfCuboid => Some(STEP_HEIGHT),
+            Self::Stairs if point.y < 0.5 => Some(1.0),
+            Self::Stairs => Some(STEP_HEIGHT),
+            Self::Lip if point.y < LIP_HEIGHT => Some(LIP_HEIGHT),
+            Self::Lip => None
+        }
+    }
 }
 
 /// Reference to terrain piece with context
@@ -226,7 +293,7 @@ impl<'terrain> Iterator for ChunkIter<'terrain> {
 }
 
 /// Chunk of terrain pieces
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Chunk(Vec<TerrainPiece>);
 
 #[derive(Copy, Clone)]
@@ -10,6 +10,7 @@ impl Plugin for ScreenPlugin {
             .init_resource::<CurrentScreen>()
             .add_event::<LoadScreenEvent>()
             .add_event::<ScreenLoadedEvent>()
+            .add_event::<ScreenLoadFailedEvent>()
             .add_system_to_stage(CoreStage::PostUpdate, handle_screen_events);
     }
 }
@@ -52,6 +53,11 @@ impl LoadScreenEvent {
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct ScreenLoadedEvent;
 
+/// Fired when screen fails to load.
+/// Plugins that fire this are expected to report the details of the failure with an event of their own.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ScreenLoadFailedEvent;
+
 /// Resource representing the current screen occupied
 #[derive(Default)]
 pub struct CurrentScreen(pub ScreenInfo);
@@ -1,4 +1,4 @@
-use crate::{screen::{ScreenInfo, LoadScreenEvent, ScreenLoadedEvent, ScreenType, Keep}, ui::UiLayers};
+use crate::{screen::{ScreenInfo, LoadScreenEvent, ScreenLoadedEvent, ScreenLoadFailedEvent, ScreenType, Keep}, ui::UiLayers};
 
 use bevy::{prelude::*, ui::FocusPolicy};
 use std::time::Duration;
@@ -122,15 +122,20 @@ fn first_half(
     }
 }
 
-/// Logic that waits for ScreenLoadedEvent before continuing to second half
+/// Logic that waits for ScreenLoadedEvent (or ScreenLoadFailedEvent) before continuing to second half
 fn waiting(
     mut reader: EventReader<ScreenLoadedEvent>,
+    mut failed_reader: EventReader<ScreenLoadFailedEvent>,
     mut trans_state: ResMut<State<TransitionState>>
 ) {
     if reader.iter().next().is_some() {
         trans_state.set(TransitionState::SecondHalf).unwrap();
         println!("Finished waiting!");
     }
+    else if failed_reader.iter().next().is_some() {
+        trans_state.set(TransitionState::SecondHalf).unwrap();
+        log::warn!("Screen failed to load, finishing transition");
+    }
 }
 
 /// Update logic for second half